@@ -0,0 +1,60 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use sieve_cache::{Operation, SieveCache};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    capacity: u8,
+    with_evict_condition: bool,
+    ops: Vec<Operation<u8, u16>>,
+}
+
+fn evict_even_keys(key: &u8, _value: &u16) -> bool {
+    key % 2 == 0
+}
+
+fuzz_target!(|input: Input| {
+    let capacity = input.capacity as usize % 16 + 1;
+    let mut cache = if input.with_evict_condition {
+        SieveCache::with_evict_condition(capacity, evict_even_keys).unwrap()
+    } else {
+        SieveCache::new(capacity).unwrap()
+    };
+    // Values we expect to find for every key that is still cached.
+    let mut shadow = HashMap::new();
+    for op in input.ops {
+        let result = op.clone().apply(&mut cache);
+        match op {
+            Operation::Insert(key, value) => {
+                if cache.contains_key(&key) {
+                    shadow.insert(key, value);
+                }
+            }
+            Operation::Get(key) => {
+                if let Some(value) = result {
+                    assert_eq!(shadow.get(&key), Some(&value));
+                }
+            }
+            Operation::GetMut(key, value) => {
+                if let Some(previous) = result {
+                    assert_eq!(shadow.insert(key, value), Some(previous));
+                }
+            }
+            Operation::Remove(key) => {
+                let expected = shadow.remove(&key);
+                if result.is_some() {
+                    assert_eq!(result, expected);
+                }
+            }
+            _ => {}
+        }
+        cache.check_consistency();
+        // Evictions are silent, so forget whatever the cache dropped.
+        shadow.retain(|key, _| cache.contains_key(key));
+        assert_eq!(shadow.len(), cache.len());
+    }
+});
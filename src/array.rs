@@ -0,0 +1,211 @@
+use std::borrow::Borrow;
+use std::mem::MaybeUninit;
+
+struct Slot<K, V> {
+    key: K,
+    value: V,
+    prev: Option<usize>,
+    next: Option<usize>,
+    visited: bool,
+}
+
+/// A SIEVE cache holding up to `N` entries inline, without any heap allocation.
+///
+/// Entries live in a fixed array and are found with a linear scan, so this is only meant for
+/// small, hot caches. Keys only need to implement `Eq`.
+pub struct ArraySieveCache<K: Eq, V, const N: usize> {
+    // `slots[..len]` are initialized, other slots are not.
+    slots: [MaybeUninit<Slot<K, V>>; N],
+    len: usize,
+    head: Option<usize>,
+    tail: Option<usize>,
+    hand: Option<usize>,
+}
+
+impl<K: Eq, V, const N: usize> Default for ArraySieveCache<K, V, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq, V, const N: usize> ArraySieveCache<K, V, N> {
+    /// Create a new, empty cache. `N` must be greater than 0.
+    pub const fn new() -> Self {
+        const { assert!(N > 0, "capacity must be greater than 0") };
+        Self {
+            slots: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+            head: None,
+            tail: None,
+            hand: None,
+        }
+    }
+
+    /// Return the capacity of the cache.
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Return the number of cached values.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` when no values are currently cached.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return `true` if there is a value in the cache mapped to by `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        self.find(key).is_some()
+    }
+
+    /// Get an immutable reference to the value mapped to by `key`, marking the entry as visited.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        Q: Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let slot = self.slot_mut(self.find(key)?);
+        slot.visited = true;
+        Some(&slot.value)
+    }
+
+    /// Get a mutable reference to the value mapped to by `key`, marking the entry as visited.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let slot = self.slot_mut(self.find(key)?);
+        slot.visited = true;
+        Some(&mut slot.value)
+    }
+
+    /// Map `key` to `value` in the cache, evicting an entry if the cache is full.
+    ///
+    /// This returns `true` if a new entry was inserted, and `false` if an existing one was updated.
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        if let Some(index) = self.find(&key) {
+            let slot = self.slot_mut(index);
+            slot.value = value;
+            slot.visited = true;
+            return false;
+        }
+        if self.len == N {
+            self.evict();
+        }
+        let index = self.len;
+        self.slots[index].write(Slot {
+            key,
+            value,
+            prev: None,
+            next: self.head,
+            visited: false,
+        });
+        self.len += 1;
+        if let Some(head) = self.head {
+            self.slot_mut(head).prev = Some(index);
+        }
+        self.head = Some(index);
+        if self.tail.is_none() {
+            self.tail = Some(index);
+        }
+        true
+    }
+
+    /// Remove the cache entry mapped to by `key`, returning its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let index = self.find(key)?;
+        Some(self.remove_slot(index).value)
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        Q: Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        (0..self.len).find(|&index| self.slot(index).key.borrow() == key)
+    }
+
+    fn slot(&self, index: usize) -> &Slot<K, V> {
+        debug_assert!(index < self.len);
+        unsafe { self.slots[index].assume_init_ref() }
+    }
+
+    fn slot_mut(&mut self, index: usize) -> &mut Slot<K, V> {
+        debug_assert!(index < self.len);
+        unsafe { self.slots[index].assume_init_mut() }
+    }
+
+    fn evict(&mut self) {
+        let mut index = self.hand.or(self.tail).expect("the cache is not empty");
+        while self.slot(index).visited {
+            self.slot_mut(index).visited = false;
+            index = self
+                .slot(index)
+                .prev
+                .or(self.tail)
+                .expect("the cache is not empty");
+        }
+        // Removing the entry under the hand moves the hand to the entry preceding it
+        self.hand = Some(index);
+        self.remove_slot(index);
+    }
+
+    /// Unlink and take the entry at `index`, moving the last slot into the hole.
+    fn remove_slot(&mut self, index: usize) -> Slot<K, V> {
+        let (prev, next) = (self.slot(index).prev, self.slot(index).next);
+        match prev {
+            Some(prev) => self.slot_mut(prev).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.slot_mut(next).prev = prev,
+            None => self.tail = prev,
+        }
+        if self.hand == Some(index) {
+            self.hand = prev;
+        }
+        let slot = unsafe { self.slots[index].assume_init_read() };
+        self.len -= 1;
+        let last = self.len;
+        if index != last {
+            let moved = unsafe { self.slots[last].assume_init_read() };
+            let (prev, next) = (moved.prev, moved.next);
+            self.slots[index].write(moved);
+            match prev {
+                Some(prev) => self.slot_mut(prev).next = Some(index),
+                None => self.head = Some(index),
+            }
+            match next {
+                Some(next) => self.slot_mut(next).prev = Some(index),
+                None => self.tail = Some(index),
+            }
+            if self.hand == Some(last) {
+                self.hand = Some(index);
+            }
+        }
+        slot
+    }
+}
+
+impl<K: Eq, V, const N: usize> Drop for ArraySieveCache<K, V, N> {
+    fn drop(&mut self) {
+        for slot in &mut self.slots[..self.len] {
+            unsafe { slot.assume_init_drop() }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+
+use crate::SieveCache;
+
+/// A [`SieveCache`] whose capacity is fixed by a const parameter, so that it implements `Default`.
+///
+/// This makes it possible to `#[derive(Default)]` on structs holding a cache. The wrapper derefs
+/// to the inner cache; `CAP` must be greater than 0.
+pub struct DefaultCapSieveCache<K: Eq + Hash + Clone, V, const CAP: usize> {
+    inner: SieveCache<K, V>,
+}
+
+impl<K: Eq + Hash + Clone, V, const CAP: usize> Default for DefaultCapSieveCache<K, V, CAP> {
+    /// Create an empty cache with capacity `CAP`, panicking if `CAP` is 0.
+    fn default() -> Self {
+        Self {
+            inner: SieveCache::new(CAP).expect("CAP must be greater than 0"),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, const CAP: usize> DefaultCapSieveCache<K, V, CAP> {
+    /// Return the wrapped cache.
+    pub fn into_inner(self) -> SieveCache<K, V> {
+        self.inner
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, const CAP: usize> Deref for DefaultCapSieveCache<K, V, CAP> {
+    type Target = SieveCache<K, V>;
+
+    fn deref(&self) -> &SieveCache<K, V> {
+        &self.inner
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, const CAP: usize> DerefMut for DefaultCapSieveCache<K, V, CAP> {
+    fn deref_mut(&mut self) -> &mut SieveCache<K, V> {
+        &mut self.inner
+    }
+}
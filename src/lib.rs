@@ -1,8 +1,35 @@
 #![doc = include_str!("../README.md")]
 
 use std::borrow::Borrow;
-use std::hash::Hash;
-use std::{collections::HashMap, ptr::NonNull};
+use std::cell::Cell;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet, TryReserveError},
+    num::NonZeroUsize,
+    ptr::NonNull,
+};
+
+mod array;
+mod default_cap;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "arbitrary")]
+mod operation;
+mod policy;
+mod sync;
+
+pub use array::ArraySieveCache;
+pub use default_cap::DefaultCapSieveCache;
+#[cfg(feature = "metrics")]
+pub use metrics::MetricsRecorder;
+#[cfg(feature = "arbitrary")]
+pub use operation::Operation;
+use policy::PolicyImpl;
+pub use policy::{EvictionPolicy, Fifo, Lru, NodeList, NodeRef, Policy, ScanDirection, Sieve};
+pub use sync::SyncSieveCache;
 
 struct Node<K: Eq + Hash + Clone, V> {
     key: K,
@@ -10,6 +37,9 @@ struct Node<K: Eq + Hash + Clone, V> {
     prev: Option<NonNull<Node<K, V>>>,
     next: Option<NonNull<Node<K, V>>>,
     visited: bool,
+    // Set on every access, and cleared by `decay_visited`
+    recent: bool,
+    weight: usize,
 }
 
 impl<K: Eq + Hash + Clone, V> Node<K, V> {
@@ -20,293 +50,5373 @@ impl<K: Eq + Hash + Clone, V> Node<K, V> {
             prev: None,
             next: None,
             visited: false,
+            recent: false,
+            weight: 0,
+        }
+    }
+}
+
+type EvictDictator<K, V> = fn(&K, &V) -> bool;
+
+type EvictDictatorExt<K, V> = fn(&K, &V, bool) -> bool;
+
+/// The evict condition of a cache, which may or may not look at the visited flag.
+enum EvictCondition<K, V> {
+    Plain(EvictDictator<K, V>),
+    WithVisited(EvictDictatorExt<K, V>),
+}
+
+impl<K, V> Clone for EvictCondition<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for EvictCondition<K, V> {}
+
+impl<K, V> EvictCondition<K, V> {
+    #[inline]
+    fn allows(&self, key: &K, value: &V, visited: bool) -> bool {
+        match self {
+            EvictCondition::Plain(cond) => cond(key, value),
+            EvictCondition::WithVisited(cond) => cond(key, value, visited),
+        }
+    }
+}
+
+type Weigher<K, V> = fn(&K, &V) -> usize;
+
+/// A callback receiving the entries evicted from a cache.
+pub type EvictListener<K, V> = Box<dyn FnMut(K, V) + Send>;
+
+/// Counters describing how a cache has been used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of lookups that found a value.
+    pub hits: u64,
+    /// Number of lookups that didn't find a value.
+    pub misses: u64,
+    /// Number of new entries inserted.
+    pub insertions: u64,
+    /// Number of entries removed to make room for new ones.
+    pub evictions: u64,
+    /// Number of times the evict condition refused to let an eviction candidate go.
+    pub condition_vetoes: u64,
+    /// Number of new entries that couldn't be inserted because no entry could be evicted.
+    pub insert_rejections: u64,
+}
+
+impl CacheStats {
+    /// Return the fraction of lookups that found a value, or `0.0` if there were no lookups.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+/// A snapshot of the size and statistics of a cache, returned by [`SieveCache::summary`].
+///
+/// Its `Display` implementation fits on one line, e.g. `SieveCache[12/64, 81% hit, 340 evicted]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CacheSummary {
+    /// The capacity of the cache, which is its maximum weight if it is weighted.
+    pub capacity: usize,
+    /// The number of cached values.
+    pub len: usize,
+    /// `len / capacity`, or `weight / max_weight` in a weighted cache, between 0 and 1 unless the
+    /// cache was filled over capacity.
+    pub utilization: f64,
+    /// The number of evictions counted in the statistics.
+    pub eviction_count: u64,
+    /// The hit ratio, or `None` if no lookup was counted.
+    pub hit_ratio: Option<f64>,
+}
+
+impl std::fmt::Display for CacheSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SieveCache[{}/{}, ", self.len, self.capacity)?;
+        if let Some(hit_ratio) = self.hit_ratio {
+            write!(f, "{:.0}% hit, ", hit_ratio * 100.0)?;
+        }
+        write!(f, "{} evicted]", self.eviction_count)
+    }
+}
+
+/// Report the heap memory owned by keys and values, for [`SieveCache::estimated_memory_usage_with`].
+pub trait Sizer<K, V> {
+    /// Return the number of heap bytes owned by `key` and `value`, excluding their inline size.
+    fn heap_size(&self, key: &K, value: &V) -> usize;
+}
+
+impl<K, V, F: Fn(&K, &V) -> usize> Sizer<K, V> for F {
+    fn heap_size(&self, key: &K, value: &V) -> usize {
+        self(key, value)
+    }
+}
+
+/// Errors returned by cache operations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CacheError {
+    /// A new entry was needed, but no existing entry could be evicted to make room.
+    EvictionBlocked,
+    /// The requested capacity was 0.
+    ZeroCapacity,
+    /// Room for the requested capacity couldn't be allocated, or its size overflowed.
+    CapacityOverflow,
+}
+
+impl std::fmt::Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::EvictionBlocked => write!(f, "no entry could be evicted"),
+            CacheError::ZeroCapacity => write!(f, "capacity must be greater than 0"),
+            CacheError::CapacityOverflow => write!(f, "unable to allocate room for the capacity"),
         }
     }
 }
 
-type EvictDictator<K: Eq + Hash + Clone, V> = fn(&K, &V) -> bool;
+impl std::error::Error for CacheError {}
+
+/// What a single eviction did, returned by [`SieveCache::evict_with_report`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvictReport<K, V> {
+    /// The evicted entry, or `None` if nothing could be evicted.
+    pub evicted: Option<(K, V)>,
+    /// The number of entries examined, from where the sweep started up to the evicted entry.
+    pub nodes_scanned: usize,
+    /// The number of visited flags cleared along the way.
+    pub flags_cleared: usize,
+}
+
+/// What is known about a cached entry, returned by [`SieveCache::metadata`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EntryMeta {
+    /// Whether the entry was accessed since the hand last passed it.
+    pub visited: bool,
+    /// How far the entry is from the hand, as returned by [`SieveCache::rank`].
+    pub rank: usize,
+    /// The weight of the entry, which is always 0 in an unweighted cache.
+    pub weight: usize,
+}
+
+/// The result of [`SieveCache::insert_result`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertOutcome {
+    /// A new entry was inserted.
+    Inserted,
+    /// The value of an existing entry was replaced.
+    Updated,
+    /// A new entry was needed, but no existing entry could be evicted to make room.
+    Rejected,
+}
 
 /// A cache based on the SIEVE eviction algorithm.
-pub struct SieveCache<K: Eq + Hash + Clone, V> {
-    map: HashMap<K, Box<Node<K, V>>>,
+///
+/// User callbacks (closures, evict conditions and eviction policies) are always called while the
+/// cache is consistent, so a panic in one of them leaves a usable cache behind.
+pub struct SieveCache<K: Eq + Hash + Clone, V, S = RandomState> {
+    map: HashMap<K, Box<Node<K, V>>, S>,
     head: Option<NonNull<Node<K, V>>>,
     tail: Option<NonNull<Node<K, V>>>,
     hand: Option<NonNull<Node<K, V>>>,
     capacity: usize,
+    growth: Option<(usize, f64)>,
     len: usize,
-    evict_condition: Option<EvictDictator<K, V>>,
+    evict_condition: Option<EvictCondition<K, V>>,
+    policy: PolicyImpl<K, V>,
+    stats: CacheStats,
+    stats_sampling: u64,
+    stats_tick: u64,
+    compaction_threshold: f64,
+    max_scan: usize,
+    scan_direction: ScanDirection,
+    weigher: Option<Weigher<K, V>>,
+    max_weight: usize,
+    weight: usize,
+    evict_listener: Option<EvictListener<K, V>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<metrics::Metrics>,
 }
 
-unsafe impl<K: Eq + Hash + Clone, V> Send for SieveCache<K, V> {}
+unsafe impl<K: Eq + Hash + Clone + Send, V: Send, S: Send> Send for SieveCache<K, V, S> {}
 
 impl<K: Eq + Hash + Clone, V> SieveCache<K, V> {
     /// Create a new cache with the given capacity.
     pub fn new(capacity: usize) -> Result<Self, &'static str> {
-        if capacity == 0 {
-            return Err("capacity must be greater than 0");
-        }
-        Ok(Self {
-            map: HashMap::with_capacity(capacity),
-            head: None,
-            tail: None,
-            hand: None,
-            capacity,
-            len: 0,
-            evict_condition: None,
-        })
+        Self::with_hasher(capacity, RandomState::new())
+    }
+
+    /// Create a new cache with the given capacity, which can't fail since it isn't 0.
+    pub fn with_nonzero_capacity(capacity: NonZeroUsize) -> Self {
+        Self::new(capacity.get()).expect("the capacity is not 0")
+    }
+
+    /// Create a cache that never retains anything.
+    ///
+    /// Insertions are accepted but immediately discarded and reported as rejected, and lookups
+    /// always miss. This lets code paths that use a cache run unchanged with caching turned off.
+    pub fn disabled() -> Self {
+        let mut cache = Self::new(1).expect("capacity is not 0");
+        cache.capacity = 0;
+        cache.map = HashMap::new();
+        cache
     }
 
     pub fn with_evict_condition(
         capacity: usize,
         evict_dictator: EvictDictator<K, V>,
     ) -> Result<Self, &'static str> {
+        let mut cache = Self::new(capacity)?;
+        cache.set_evict_condition(evict_dictator);
+        Ok(cache)
+    }
+
+    /// Create a new cache with the given capacity, whose evict condition also receives the visited
+    /// flag of the candidate entry.
+    ///
+    /// SIEVE skips visited entries, so it only offers one to the condition once an eviction has
+    /// examined more than `max_scan` entries (see [`set_max_scan`](Self::set_max_scan)): this lets
+    /// the condition keep protecting visited entries in that case. Without a scan bound, SIEVE
+    /// always passes `false`. Custom eviction policies may offer visited entries at any time.
+    pub fn with_evict_condition_ext(
+        capacity: usize,
+        evict_dictator: EvictDictatorExt<K, V>,
+    ) -> Result<Self, &'static str> {
+        let mut cache = Self::new(capacity)?;
+        cache.set_evict_condition_ext(evict_dictator);
+        Ok(cache)
+    }
+
+    /// Create a cache bounded by the total weight of its entries rather than by their number.
+    ///
+    /// The weight of an entry is computed by `weigher` when it is inserted or replaced through
+    /// [`insert`](Self::insert); changing a value in place doesn't update it. Entries are evicted
+    /// until the total weight fits in `max_weight`, and an entry heavier than `max_weight` is
+    /// rejected; replacing a value with such a heavy one removes the entry. The capacity of the
+    /// cache is set to `max_weight`, and changing the capacity changes the maximum weight.
+    ///
+    /// A weight of 0 is counted as 1, so that weightless entries still take room and can't pile up
+    /// without ever causing evictions.
+    pub fn with_weigher(max_weight: usize, weigher: Weigher<K, V>) -> Result<Self, &'static str> {
+        // Don't preallocate room for `max_weight` entries, which can be a byte count
+        let mut cache = Self::new(1)?;
+        cache.map = HashMap::new();
+        cache.set_weigher(max_weight, weigher)?;
+        Ok(cache)
+    }
+
+    /// Create a cache holding at most `max_bytes` bytes, as reported by `weigher` for each entry.
+    ///
+    /// This is [`with_weigher`](Self::with_weigher) with weights understood as byte sizes.
+    pub fn with_capacity_bytes(
+        max_bytes: usize,
+        weigher: Weigher<K, V>,
+    ) -> Result<Self, CacheError> {
+        Self::with_weigher(max_bytes, weigher).map_err(|_| CacheError::ZeroCapacity)
+    }
+
+    /// Create a new cache with the given capacity, whose evictions scan at most `max_scan` entries.
+    ///
+    /// Once an eviction has examined `max_scan` entries without finding one to evict, the next
+    /// candidate is evicted even if it was visited, bounding the latency of insertions when nearly every entry
+    /// is visited. The evict condition is still honored.
+    pub fn with_max_scan(capacity: usize, max_scan: usize) -> Result<Self, &'static str> {
+        let mut cache = Self::new(capacity)?;
+        cache.set_max_scan(max_scan);
+        Ok(cache)
+    }
+
+    /// Create a new cache with the given capacity, that only counts one lookup out of `rate` in
+    /// its statistics.
+    ///
+    /// Each sampled lookup is counted `rate` times, so that [`stats`](Self::stats) reports
+    /// approximate hit and miss counts while most lookups skip the bookkeeping. Lookups are
+    /// sampled at a fixed interval, so a workload that repeats with a period matching `rate`
+    /// skews the figures. Insertions and evictions are always counted exactly.
+    pub fn with_stats_sampling(capacity: usize, rate: u64) -> Result<Self, &'static str> {
+        let mut cache = Self::new(capacity)?;
+        cache.set_stats_sampling(rate)?;
+        Ok(cache)
+    }
+
+    /// Create a cache from pairs returned by [`serialize_top`](SieveCache::serialize_top).
+    ///
+    /// The pairs are expected from the newest entry to the oldest, and keep that order.
+    pub fn from_pairs<I: IntoIterator<Item = (K, V)>>(
+        capacity: usize,
+        pairs: I,
+    ) -> Result<Self, &'static str> {
+        let mut cache = Self::new(capacity)?;
+        cache.append_in_order(pairs.into_iter().map(|(key, value)| (key, value, false)));
+        Ok(cache)
+    }
+
+    /// Create a cache with the given capacity, seeded with `entries`.
+    ///
+    /// The entries are inserted in order as with [`insert`](Self::insert), so the last ones end up
+    /// at the head, and older ones get evicted if there are more entries than `capacity`.
+    pub fn with_initial_entries<I: IntoIterator<Item = (K, V)>>(
+        capacity: usize,
+        entries: I,
+    ) -> Result<Self, CacheError> {
+        let mut cache = Self::new(capacity).map_err(|_| CacheError::ZeroCapacity)?;
+        cache.extend(entries);
+        Ok(cache)
+    }
+
+    /// Create a new cache with the given capacity, whose hand sweeps entries in the given direction.
+    ///
+    /// The default, [`ScanDirection::TailToHead`], moves from the oldest entries toward the newest
+    /// ones. This only affects SIEVE and FIFO; LRU always evicts the least recently used entry.
+    pub fn with_scan_direction(
+        capacity: usize,
+        direction: ScanDirection,
+    ) -> Result<Self, &'static str> {
+        let mut cache = Self::new(capacity)?;
+        cache.set_scan_direction(direction);
+        Ok(cache)
+    }
+
+    /// Create a cache that grows instead of evicting, until it reaches `max` entries.
+    ///
+    /// When a new entry doesn't fit, the capacity is multiplied by `factor` (and grows by at least
+    /// one entry), up to `max`. Once the capacity has reached `max`, entries are evicted as usual.
+    /// The capacity never shrinks back on its own.
+    pub fn with_growth_policy(
+        initial: usize,
+        max: usize,
+        factor: f64,
+    ) -> Result<Self, &'static str> {
+        let mut cache = Self::new(initial)?;
+        cache.set_growth_policy(max, factor)?;
+        Ok(cache)
+    }
+
+    /// Create a new cache with the given capacity, evicting entries according to `policy`.
+    pub fn with_policy(capacity: usize, policy: Policy) -> Result<Self, &'static str> {
+        let mut cache = Self::new(capacity)?;
+        cache.set_policy(policy);
+        Ok(cache)
+    }
+
+    /// Create a new cache with the given capacity, evicting entries according to a custom `policy`.
+    ///
+    /// The policy is cloned along with the cache.
+    pub fn with_eviction_policy<P>(capacity: usize, policy: P) -> Result<Self, &'static str>
+    where
+        P: EvictionPolicy<K, V> + Clone + Send + 'static,
+    {
+        let mut cache = Self::new(capacity)?;
+        cache.set_eviction_policy(policy);
+        Ok(cache)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> SieveCache<K, V, S> {
+    /// Create a new cache with the given capacity, using `hash_builder` to hash keys.
+    pub fn with_hasher(capacity: usize, hash_builder: S) -> Result<Self, &'static str> {
         if capacity == 0 {
             return Err("capacity must be greater than 0");
         }
         Ok(Self {
-            map: HashMap::with_capacity(capacity),
+            map: HashMap::with_capacity_and_hasher(capacity, hash_builder),
             head: None,
             tail: None,
             hand: None,
             capacity,
+            growth: None,
             len: 0,
-            evict_condition: Some(evict_dictator),
+            evict_condition: None,
+            policy: PolicyImpl::Sieve(Sieve),
+            stats: CacheStats::default(),
+            stats_sampling: 1,
+            stats_tick: 0,
+            compaction_threshold: 0.25,
+            max_scan: usize::MAX,
+            scan_direction: ScanDirection::TailToHead,
+            weigher: None,
+            max_weight: usize::MAX,
+            weight: 0,
+            evict_listener: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
         })
     }
 
-    /// Return the capacity of the cache.
+    /// Return a reference to the hasher used by the cache to hash keys.
     #[inline]
-    pub fn capacity(&self) -> usize {
-        self.capacity
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
     }
 
-    /// Return the number of cached values.
-    #[inline]
-    pub fn len(&self) -> usize {
-        self.len
+    /// Return the name of the eviction policy used by the cache, such as `"SIEVE"`.
+    pub fn eviction_policy_name(&self) -> &'static str {
+        self.policy.name()
     }
 
-    /// Return `true` when no values are currently cached.
-    #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    /// Evict entries according to `policy` from now on, like
+    /// [`with_policy`](SieveCache::with_policy) does.
+    ///
+    /// Cached entries are kept, along with their visited flags.
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy.into();
     }
 
-    /// Return `true` if there is a value in the cache mapped to by `key`.
-    #[inline]
-    pub fn contains_key<Q>(&mut self, key: &Q) -> bool
+    /// Evict entries according to a custom `policy` from now on, like
+    /// [`with_eviction_policy`](SieveCache::with_eviction_policy) does.
+    ///
+    /// Cached entries are kept, and passed to the policy as insertions from the oldest to the newest.
+    pub fn set_eviction_policy<P>(&mut self, policy: P)
     where
-        Q: Hash + Eq + ?Sized,
-        K: Borrow<Q>,
+        P: EvictionPolicy<K, V> + Clone + Send + 'static,
     {
-        self.map.contains_key(key)
+        self.policy = PolicyImpl::Custom(Box::new(policy));
+        let mut node = self.tail;
+        while let Some(node_) = node {
+            self.policy.on_insert(node_);
+            node = unsafe { node_.as_ref().prev };
+        }
     }
 
-    /// Get an immutable reference to the value in the cache mapped to by `key`.
-    ///
-    /// If no value exists for `key`, this returns `None`.
-    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
-    where
-        Q: Hash + Eq + ?Sized,
-        K: Borrow<Q>,
-    {
-        let node_ = self.map.get_mut(key)?;
-        node_.visited = true;
-        Some(&node_.value)
+    /// Only evict entries for which `evict_dictator` returns `true`, replacing the previous evict
+    /// condition, like [`with_evict_condition`](SieveCache::with_evict_condition) does.
+    pub fn set_evict_condition(&mut self, evict_dictator: EvictDictator<K, V>) {
+        self.evict_condition = Some(EvictCondition::Plain(evict_dictator));
     }
 
-    /// Get a mutable reference to the value in the cache mapped to by `key`.
+    /// Like [`set_evict_condition`](Self::set_evict_condition), with a condition that also receives
+    /// the visited flag of the candidate entry, as with
+    /// [`with_evict_condition_ext`](SieveCache::with_evict_condition_ext).
+    pub fn set_evict_condition_ext(&mut self, evict_dictator: EvictDictatorExt<K, V>) {
+        self.evict_condition = Some(EvictCondition::WithVisited(evict_dictator));
+    }
+
+    /// Remove the evict condition, so that any entry can be evicted again.
+    pub fn clear_evict_condition(&mut self) {
+        self.evict_condition = None;
+    }
+
+    /// Scan at most `max_scan` entries per eviction, like
+    /// [`with_max_scan`](SieveCache::with_max_scan) does. `usize::MAX` removes the bound.
+    pub fn set_max_scan(&mut self, max_scan: usize) {
+        self.max_scan = max_scan;
+    }
+
+    /// Sweep entries in the given direction from now on, like
+    /// [`with_scan_direction`](SieveCache::with_scan_direction) does.
+    pub fn set_scan_direction(&mut self, direction: ScanDirection) {
+        self.scan_direction = direction;
+    }
+
+    /// Only count one lookup out of `rate` in the statistics, like
+    /// [`with_stats_sampling`](SieveCache::with_stats_sampling) does. A rate of 1 counts every lookup.
     ///
-    /// If no value exists for `key`, this returns `None`.
-    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
-    where
-        Q: Hash + Eq + ?Sized,
-        K: Borrow<Q>,
-    {
-        let node_ = self.map.get_mut(key)?;
-        node_.visited = true;
-        Some(&mut node_.value)
+    /// This returns an error if `rate` is 0.
+    pub fn set_stats_sampling(&mut self, rate: u64) -> Result<(), &'static str> {
+        if rate == 0 {
+            return Err("rate must be greater than 0");
+        }
+        self.stats_sampling = rate;
+        self.stats_tick = 0;
+        Ok(())
     }
 
-    /// Map `key` to `value` in the cache, possibly evicting old entries.
+    /// Grow instead of evicting, until the capacity reaches `max` entries, like
+    /// [`with_growth_policy`](SieveCache::with_growth_policy) does.
     ///
-    /// This method returns `(true, true)` when this is a new entry, and `(false, true)` if an existing entry was
-    /// updated. Last value of the pair is false if it failed to evict an entry to insert a new one.
-    pub fn insert(&mut self, key: K, value: V) -> (bool, bool) {
-        let node = self.map.get_mut(&key);
-        if let Some(node_) = node {
-            node_.visited = true;
-            node_.value = value;
-            return (false, true);
+    /// The growth policy counts entries, so this returns an error for a weighted cache, as well as
+    /// if `max` is lower than the current capacity or `factor` isn't greater than 1.
+    pub fn set_growth_policy(&mut self, max: usize, factor: f64) -> Result<(), &'static str> {
+        if self.weigher.is_some() {
+            return Err("a weighted cache can't have a growth policy");
         }
-        if self.len >= self.capacity {
-            if !self.evict() {
-                return (false, false);
-            }
+        if max < self.capacity {
+            return Err("max must not be lower than the initial capacity");
         }
-        let node = Box::new(Node::new(key.clone(), value));
-        self.add_node(NonNull::from(node.as_ref()));
-        debug_assert!(!node.visited);
-        self.map.insert(key, node);
-        debug_assert!(self.len < self.capacity);
-        self.len += 1;
-        (true, true)
+        if factor.is_nan() || factor <= 1.0 {
+            return Err("factor must be greater than 1");
+        }
+        self.growth = Some((max, factor));
+        Ok(())
     }
 
-    /// Remove the cache entry mapped to by `key`.
+    /// Bound the cache by the total weight of its entries, as computed by `weigher`, like
+    /// [`with_weigher`](SieveCache::with_weigher) does.
     ///
-    /// This method returns the value removed from the cache. If `key` did not map to any value,
-    /// then this returns `None`.
-    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
-    where
-        K: Borrow<Q>,
-        Q: Eq + Hash + ?Sized,
-    {
-        let node_ = self.map.get_mut(key)?;
-        let node__ = NonNull::from(node_.as_ref());
-        if self.hand == Some(node__) {
-            self.hand = node_.as_ref().prev;
+    /// Cached entries are weighed again, and evicted until they fit in `max_weight`, which becomes
+    /// the capacity of the cache. This returns an error if `max_weight` is 0, or if the cache has a
+    /// growth policy.
+    pub fn set_weigher(
+        &mut self,
+        max_weight: usize,
+        weigher: Weigher<K, V>,
+    ) -> Result<(), &'static str> {
+        if max_weight == 0 {
+            return Err("max_weight must be greater than 0");
         }
-        let value = self.map.remove(key).map(|node| node.value);
-        self.remove_node(node__);
-        debug_assert!(self.len > 0);
-        self.len -= 1;
-        value
+        if self.growth.is_some() {
+            return Err("a weighted cache can't have a growth policy");
+        }
+        self.weigher = Some(weigher);
+        self.max_weight = max_weight;
+        if self.capacity != 0 {
+            self.capacity = max_weight;
+        }
+        self.weight = 0;
+        for node in self.map.values_mut() {
+            node.weight = weigher(&node.key, &node.value).max(1);
+            self.weight += node.weight;
+        }
+        self.trim_weight();
+        Ok(())
     }
 
-    fn add_node(&mut self, mut node: NonNull<Node<K, V>>) {
-        unsafe {
-            node.as_mut().next = self.head;
-            node.as_mut().prev = None;
-            if let Some(mut head) = self.head {
-                head.as_mut().prev = Some(node);
+    /// Call `listener` with every entry evicted from now on, replacing the previous listener.
+    ///
+    /// Only entries evicted to make room are reported, not the ones removed explicitly. The
+    /// listener isn't cloned along with the cache.
+    pub fn set_evict_listener(&mut self, listener: EvictListener<K, V>) {
+        self.evict_listener = Some(listener);
+    }
+
+    /// Detach the eviction listener, returning it.
+    pub fn take_evict_listener(&mut self) -> Option<EvictListener<K, V>> {
+        self.evict_listener.take()
+    }
+
+    /// Report the activity of the cache to `recorder` from now on, replacing the previous one.
+    ///
+    /// The counters `{prefix}hits`, `{prefix}misses` and `{prefix}evictions` follow the
+    /// [statistics](Self::stats) of the cache, including their sampling, and the gauge `{prefix}len`
+    /// is set whenever the number of entries changes. Clones of the cache report to the same
+    /// recorder.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics_recorder(
+        &mut self,
+        prefix: &str,
+        recorder: std::sync::Arc<dyn MetricsRecorder>,
+    ) {
+        let metrics = metrics::Metrics::new(prefix, recorder);
+        metrics.len(self.len);
+        self.metrics = Some(metrics);
+    }
+
+    /// Stop reporting metrics.
+    #[cfg(feature = "metrics")]
+    pub fn clear_metrics_recorder(&mut self) {
+        self.metrics = None;
+    }
+
+    /// Return the capacity of the cache.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the capacity of the cache.
+    ///
+    /// Growing the cache only reserves room in the map: cached entries, their visited flags and
+    /// the hand are left untouched, so eviction carries on exactly where it was. Shrinking the
+    /// cache evicts entries until they fit.
+    ///
+    /// In a weighted cache, `capacity` is the new maximum weight, and no room is reserved since it
+    /// doesn't count entries.
+    ///
+    /// This returns an error if `capacity` is 0, or if the evict condition prevents shrinking the
+    /// cache. In the latter case the previous capacity is kept, but some entries may have been evicted.
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), &'static str> {
+        if capacity == 0 {
+            return Err("capacity must be greater than 0");
+        }
+        while self.exceeds(capacity) {
+            if !self.evict() {
+                return Err("unable to evict enough entries to shrink the cache");
             }
         }
-        self.head = Some(node);
-        if self.tail.is_none() {
-            self.tail = self.head;
+        if capacity > self.capacity && self.weigher.is_none() {
+            self.map.reserve(capacity - self.len);
         }
+        self.set_capacity_unchecked(capacity);
+        Ok(())
     }
 
-    fn remove_node(&mut self, node: NonNull<Node<K, V>>) {
-        unsafe {
-            if let Some(mut prev) = node.as_ref().prev {
-                prev.as_mut().next = node.as_ref().next;
-            } else {
-                self.head = node.as_ref().next;
-            }
-            if let Some(mut next) = node.as_ref().next {
-                next.as_mut().prev = node.as_ref().prev;
-            } else {
-                self.tail = node.as_ref().prev;
+    /// Like [`set_capacity`](Self::set_capacity), but return an error instead of aborting if
+    /// growing the map fails.
+    ///
+    /// Room for a larger capacity is reserved before anything else, so the cache is left untouched
+    /// on [`CacheError::CapacityOverflow`].
+    pub fn try_set_capacity(&mut self, capacity: usize) -> Result<(), CacheError> {
+        if capacity == 0 {
+            return Err(CacheError::ZeroCapacity);
+        }
+        if capacity > self.len && self.weigher.is_none() {
+            self.map
+                .try_reserve(capacity - self.len)
+                .map_err(|_| CacheError::CapacityOverflow)?;
+        }
+        while self.exceeds(capacity) {
+            if !self.evict() {
+                return Err(CacheError::EvictionBlocked);
             }
         }
+        self.set_capacity_unchecked(capacity);
+        Ok(())
     }
 
-    fn evict(&mut self) -> bool {
-        let mut node = self.hand.or(self.tail);
-        let len = self.len();
-        let mut visited = 0;
-        while node.is_some() {
-            if visited >= len {
-                // We cannot evict anything
-                return false;
-            }
-            let mut node_ = node.unwrap();
-            visited += 1;
-            unsafe {
-                let node_ref = node_.as_ref();
-                if !node_ref.visited && self.evict_condition.is_none() {
-                    break;
-                }
-                if !node_ref.visited
-                    && self.evict_condition.is_some()
-                        & self.evict_condition.unwrap()(&node_ref.key, &node_ref.value)
-                {
-                    break;
-                }
-                node_.as_mut().visited = false;
-                if node_.as_ref().prev.is_some() {
-                    node = node_.as_ref().prev;
-                } else {
-                    node = self.tail;
-                }
-            }
+    /// Return `true` if the entries don't fit in `capacity`, which bounds their total weight in a
+    /// weighted cache and their number otherwise.
+    fn exceeds(&self, capacity: usize) -> bool {
+        if self.weigher.is_some() {
+            self.weight > capacity
+        } else {
+            self.len > capacity
         }
-        if let Some(node_) = node {
-            unsafe {
-                self.hand = node_.as_ref().prev;
-                self.map.remove(&node_.as_ref().key);
-            }
-            self.remove_node(node_);
-            debug_assert!(self.len > 0);
-            self.len -= 1;
+    }
+
+    /// Change the capacity, and the maximum weight along with it in a weighted cache.
+    fn set_capacity_unchecked(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        if self.weigher.is_some() {
+            self.max_weight = capacity;
         }
-        true
     }
-}
 
-#[test]
-fn test() {
-    let mut cache = SieveCache::new(3).unwrap();
-    assert!(cache.insert("foo".to_string(), "foocontent".to_string()).0);
-    assert!(cache.insert("bar".to_string(), "barcontent".to_string()).0);
-    cache.remove("bar");
-    assert!(
-        cache
-            .insert("bar2".to_string(), "bar2content".to_string())
-            .0
-    );
-    assert!(
-        cache
-            .insert("bar3".to_string(), "bar3content".to_string())
+    /// Remove every entry, keeping the configuration and the statistics of the cache.
+    ///
+    /// The eviction listener isn't called for the removed entries.
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.tail = None;
+        self.hand = None;
+        self.len = 0;
+        self.weight = 0;
+        self.map.clear();
+        self.report_len();
+    }
+
+    /// Remove every entry, releasing the memory of the map unless `keep_allocation` is `true`.
+    ///
+    /// Keeping the allocation, like [`clear`](Self::clear) does, avoids reallocating when the cache
+    /// fills up again; releasing it is better for caches that stay small for a while.
+    pub fn clear_with(&mut self, keep_allocation: bool) {
+        self.clear();
+        if !keep_allocation {
+            self.map.shrink_to_fit();
+        }
+    }
+
+    /// Remove every entry and reset the statistics, keeping the configuration of the cache.
+    ///
+    /// This puts the cache back in the state it was created in, to start a fresh run.
+    pub fn reset(&mut self) {
+        self.clear();
+        self.reset_stats();
+        self.stats_tick = 0;
+    }
+
+    /// Remove every entry and change the capacity, resizing the map for the new capacity.
+    ///
+    /// In a weighted cache, `capacity` is the new maximum weight, and the memory of the map is
+    /// released instead since the number of entries it will hold is unknown.
+    ///
+    /// This returns an error if `capacity` is 0, in which case the cache is left untouched.
+    pub fn clear_and_resize(&mut self, capacity: usize) -> Result<(), CacheError> {
+        if capacity == 0 {
+            return Err(CacheError::ZeroCapacity);
+        }
+        self.clear();
+        if self.weigher.is_some() {
+            self.map.shrink_to_fit();
+        } else {
+            self.map.shrink_to(capacity);
+            self.map.reserve(capacity);
+        }
+        self.set_capacity_unchecked(capacity);
+        Ok(())
+    }
+
+    /// Reserve room in the map for at least `additional` more entries, ahead of bulk insertions.
+    ///
+    /// This doesn't change the capacity of the cache.
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
+
+    /// Reserve room in the map for `additional` more entries, without over-allocating on purpose.
+    ///
+    /// The map always rounds its number of buckets up, so this is currently the same as
+    /// [`reserve`](Self::reserve); it is kept separate for parity with the standard collections.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
+
+    /// Try to reserve room in the map for at least `additional` more entries.
+    ///
+    /// Unlike [`reserve`](Self::reserve), this returns an error instead of aborting if the
+    /// allocation fails or the size overflows.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.map.try_reserve(additional)
+    }
+
+    /// Set the fraction of the map's allocated capacity below which [`maybe_compact`](Self::maybe_compact)
+    /// releases memory. The default is `0.25`, and `0.0` disables compaction.
+    pub fn set_compaction_threshold(&mut self, ratio: f64) {
+        self.compaction_threshold = ratio.clamp(0.0, 1.0);
+    }
+
+    /// Shrink the internal map if the number of cached values fell below the compaction threshold.
+    ///
+    /// After many removals, the map keeps the memory it allocated for its largest size. This
+    /// releases it, at the cost of reallocating if the cache grows again.
+    pub fn maybe_compact(&mut self) {
+        if (self.len as f64) < self.map.capacity() as f64 * self.compaction_threshold {
+            self.map.shrink_to(self.len);
+        }
+    }
+
+    /// Shrink the allocation of the map so it can hold at least `min_capacity` entries, and never
+    /// fewer than the number of cached entries.
+    ///
+    /// This doesn't change the capacity of the cache.
+    pub fn shrink_map_to(&mut self, min_capacity: usize) {
+        self.map.shrink_to(min_capacity);
+    }
+
+    /// Return an estimate of the memory used by the cache, in bytes.
+    ///
+    /// This counts the cache itself, one node allocation per entry, and every slot of the map
+    /// (a key, a pointer and a control byte). It doesn't account for memory owned by the keys
+    /// and values; use [`estimated_memory_usage_with`](Self::estimated_memory_usage_with) for that.
+    pub fn estimated_memory_usage(&self) -> usize {
+        let node_size = std::mem::size_of::<Node<K, V>>();
+        let slot_size = std::mem::size_of::<(K, Box<Node<K, V>>)>() + 1;
+        std::mem::size_of::<Self>() + self.len * node_size + self.map.capacity() * slot_size
+    }
+
+    /// Return an estimate of the memory used by the cache, in bytes, including the heap memory
+    /// owned by keys and values as reported by `sizer`.
+    pub fn estimated_memory_usage_with<Z: Sizer<K, V>>(&self, sizer: &Z) -> usize {
+        let owned: usize = self
+            .map
+            .values()
+            .map(|node| sizer.heap_size(&node.key, &node.value))
+            .sum();
+        self.estimated_memory_usage() + owned
+    }
+
+    /// Return the number of entries the underlying map can hold without reallocating.
+    ///
+    /// Unlike [`capacity`](Self::capacity), this reflects the actual allocation, which can be
+    /// larger than the number of entries the cache is allowed to hold.
+    #[inline]
+    pub fn map_capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    /// Return the number of cached values.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` when no values are currently cached.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return `true` when the cache is at capacity, so that inserting a new key will evict an entry.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len >= self.capacity || self.weight >= self.max_weight
+    }
+
+    /// Return how saturated the cache is, growing faster and faster as it fills up.
+    ///
+    /// This is `len / (capacity - len + 1)`: 0 for an empty cache, about 1 when half full, and
+    /// `capacity` when full, so that autoscalers can react well before the cache is full.
+    /// Entries beyond the capacity count as a full cache. A weighted cache uses its total weight
+    /// and its maximum weight instead.
+    pub fn pressure(&self) -> f64 {
+        let (used, capacity) = self.usage();
+        used as f64 / (capacity.saturating_sub(used) + 1) as f64
+    }
+
+    /// Return how much of the capacity is used, and the capacity, by weight in a weighted cache.
+    fn usage(&self) -> (usize, usize) {
+        if self.weigher.is_some() {
+            (self.weight, self.max_weight)
+        } else {
+            (self.len, self.capacity)
+        }
+    }
+
+    /// Return the total weight of the cached entries, which is always 0 in an unweighted cache.
+    #[inline]
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Return the maximum total weight of the cache, or `usize::MAX` if it isn't weighted.
+    #[inline]
+    pub fn max_weight(&self) -> usize {
+        self.max_weight
+    }
+
+    /// Count the entries whose weight falls in each of the ranges delimited by `buckets`.
+    ///
+    /// `buckets` are sorted inclusive upper bounds: the count at index `i` is the number of entries
+    /// weighing more than `buckets[i - 1]` and at most `buckets[i]`, and the last of the
+    /// `buckets.len() + 1` counts is for entries heavier than every bound. Weights are computed
+    /// with the weigher from the current values; in an unweighted cache every entry weighs 1.
+    pub fn weight_histogram(&self, buckets: &[usize]) -> Vec<usize> {
+        debug_assert!(buckets.windows(2).all(|w| w[0] <= w[1]));
+        let mut counts = vec![0; buckets.len() + 1];
+        for node in self.map.values() {
+            let weight = self
+                .weigher
+                .map_or(1, |weigher| weigher(&node.key, &node.value));
+            counts[buckets.partition_point(|&bound| bound < weight)] += 1;
+        }
+        counts
+    }
+
+    /// Return the usage statistics accumulated since the cache was created or the stats were last reset.
+    #[inline]
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Reset all usage statistics to zero.
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    /// Return a summary of the size and statistics of the cache, for periodic logging.
+    pub fn summary(&self) -> CacheSummary {
+        let lookups = self.stats.hits + self.stats.misses;
+        let (used, capacity) = self.usage();
+        CacheSummary {
+            capacity: self.capacity,
+            len: self.len,
+            utilization: if capacity == 0 {
+                0.0
+            } else {
+                used as f64 / capacity as f64
+            },
+            eviction_count: self.stats.evictions,
+            hit_ratio: (lookups > 0).then(|| self.stats.hit_ratio()),
+        }
+    }
+
+    /// Return the usage statistics accumulated since the last reset, and reset them in the same step.
+    ///
+    /// This is convenient to report statistics over consecutive windows.
+    #[inline]
+    pub fn take_stats(&mut self) -> CacheStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// Return `true` if there is a value in the cache mapped to by `key`.
+    #[inline]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Return `true` if every key in `keys` maps to a value in the cache.
+    pub fn contains_keys<'a, Q, I>(&self, keys: I) -> bool
+    where
+        Q: Hash + Eq + ?Sized + 'a,
+        K: Borrow<Q>,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        keys.into_iter().all(|key| self.map.contains_key(key))
+    }
+
+    /// Return `true` if at least one key in `keys` maps to a value in the cache.
+    pub fn contains_any<'a, Q, I>(&self, keys: I) -> bool
+    where
+        Q: Hash + Eq + ?Sized + 'a,
+        K: Borrow<Q>,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        keys.into_iter().any(|key| self.map.contains_key(key))
+    }
+
+    /// Return, for each key in `keys`, whether it maps to a value in the cache, in the same order.
+    pub fn contains_each<'a, Q, I>(&self, keys: I) -> Vec<bool>
+    where
+        Q: Hash + Eq + ?Sized + 'a,
+        K: Borrow<Q>,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        keys.into_iter()
+            .map(|key| self.map.contains_key(key))
+            .collect()
+    }
+
+    /// Return the keys satisfying `pred`, in no particular order.
+    ///
+    /// Values aren't looked at and no entry is marked as visited.
+    pub fn keys_matching<F: Fn(&K) -> bool>(&self, pred: F) -> Vec<&K> {
+        self.map.keys().filter(|key| pred(key)).collect()
+    }
+
+    /// Return the visited flag of the entry mapped to by `key`, or `None` if there is none.
+    ///
+    /// This doesn't count as an access.
+    pub fn visited<Q>(&self, key: &Q) -> Option<bool>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        self.map.get(key).map(|node| node.visited)
+    }
+
+    /// Get an immutable reference to the value in the cache mapped to by `key`.
+    ///
+    /// If no value exists for `key`, this returns `None`.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let node_ = self.access(key)?;
+        Some(unsafe { &(*node_.as_ptr()).value })
+    }
+
+    /// Apply `f` to the value mapped to by `key`, marking the entry as visited.
+    ///
+    /// This is handy when the cache sits behind a lock, since no reference has to outlive the call.
+    pub fn get_with<Q, R, F: FnOnce(&V) -> R>(&mut self, key: &Q, f: F) -> Option<R>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        self.get(key).map(f)
+    }
+
+    /// Return a clone of the value mapped to by `key`, marking the entry as visited.
+    ///
+    /// This is convenient when a reference can't be handed out, e.g. from behind a lock.
+    pub fn get_cloned<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+        V: Clone,
+    {
+        self.get(key).cloned()
+    }
+
+    /// Get an immutable reference to the value mapped to by `key`, without marking it as visited.
+    ///
+    /// This doesn't count as an access, and only needs a shared reference to the cache.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        self.map.get(key).map(|node| &node.value)
+    }
+
+    /// Apply `f` to the value mapped to by `key`, without marking the entry as visited.
+    ///
+    /// This doesn't count as an access, and only needs a shared reference to the cache.
+    pub fn peek_with<Q, R, F: FnOnce(&V) -> R>(&self, key: &Q, f: F) -> Option<R>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        self.map.get(key).map(|node| f(&node.value))
+    }
+
+    /// Get a mutable reference to the value in the cache mapped to by `key`.
+    ///
+    /// If no value exists for `key`, this returns `None`.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let node_ = self.access(key)?;
+        Some(unsafe { &mut (*node_.as_ptr()).value })
+    }
+
+    /// Get a guard giving mutable access to the value mapped to by `key`.
+    ///
+    /// Unlike [`get_mut`](Self::get_mut), the entry is only marked as visited the first time the
+    /// guard is dereferenced, so a lookup whose result ends up unused doesn't protect the entry.
+    pub fn access_mut<Q>(&mut self, key: &Q) -> Option<AccessGuard<'_, K, V, S>>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let node = self
+            .map
+            .get_mut(key)
+            .map(|node| NonNull::from(node.as_mut()));
+        self.record_lookup(node.is_some());
+        Some(AccessGuard {
+            node: node?,
+            cache: NonNull::from(self),
+            accessed: Cell::new(false),
+            marker: PhantomData,
+        })
+    }
+
+    /// Get mutable references to the values mapped to by two distinct keys at once.
+    ///
+    /// Both entries are marked as visited. Since the references can't alias, this returns
+    /// `(None, None)` without looking anything up if `a == b`.
+    pub fn get_pair_mut<Q>(&mut self, a: &Q, b: &Q) -> (Option<&mut V>, Option<&mut V>)
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        if a == b {
+            return (None, None);
+        }
+        let a = self.access(a);
+        let b = self.access(b);
+        debug_assert!(a.is_none() || a != b);
+        unsafe {
+            (
+                a.map(|node_| &mut (*node_.as_ptr()).value),
+                b.map(|node_| &mut (*node_.as_ptr()).value),
+            )
+        }
+    }
+
+    /// Swap the values mapped to by `a` and `b`, marking both entries as visited.
+    ///
+    /// The entries keep their keys and positions, only the values move. This returns `false` and
+    /// changes nothing if either key is missing, or if `a == b`.
+    pub fn swap<Q>(&mut self, a: &Q, b: &Q) -> bool
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        if a == b || !self.map.contains_key(a) || !self.map.contains_key(b) {
+            return false;
+        }
+        let (Some(a), Some(b)) = (self.access(a), self.access(b)) else {
+            unreachable!("both keys are in the map");
+        };
+        unsafe {
+            std::mem::swap(&mut (*a.as_ptr()).value, &mut (*b.as_ptr()).value);
+            std::mem::swap(&mut (*a.as_ptr()).weight, &mut (*b.as_ptr()).weight);
+        }
+        true
+    }
+
+    /// Get mutable references to the values mapped to by `keys`, keyed by the keys that were found.
+    ///
+    /// Each found entry is marked as visited. Keys that appear several times in `keys` are only
+    /// looked up once, so the references never alias; missing keys are absent from the result.
+    pub fn get_many_mut_map<Q>(&mut self, keys: &[Q]) -> HashMap<Q, &mut V>
+    where
+        Q: Hash + Eq + Clone,
+        K: Borrow<Q>,
+    {
+        let mut found = HashMap::with_capacity(keys.len());
+        for key in keys {
+            if found.contains_key(key) {
+                continue;
+            }
+            if let Some(node_) = self.access(key) {
+                found.insert(key.clone(), unsafe { &mut (*node_.as_ptr()).value });
+            }
+        }
+        found
+    }
+
+    /// Map `key` to `value` in the cache, possibly evicting old entries.
+    ///
+    /// This method returns `(true, true)` when this is a new entry, and `(false, true)` if an existing entry was
+    /// updated. Last value of the pair is false if it failed to evict an entry to insert a new one.
+    pub fn insert(&mut self, key: K, value: V) -> (bool, bool) {
+        match self.insert_result(key, value) {
+            InsertOutcome::Inserted => (true, true),
+            InsertOutcome::Updated => (false, true),
+            InsertOutcome::Rejected => (false, false),
+        }
+    }
+
+    /// Map `key` to `value` in the cache, possibly evicting old entries.
+    ///
+    /// This is the same as [`insert`](Self::insert), with a self-describing result.
+    ///
+    /// In a weighted cache, updating an entry with a value heavier than the maximum weight removes
+    /// the entry, and the update is reported as rejected.
+    pub fn insert_result(&mut self, key: K, value: V) -> InsertOutcome {
+        if let Some(node) = self.map.get_mut(&key) {
+            let node_ = NonNull::from(node.as_mut());
+            if !self.replace_value(node_, value) {
+                return InsertOutcome::Rejected;
+            }
+            self.touch(node_);
+            self.trim_weight();
+            return InsertOutcome::Updated;
+        }
+        if self.insert_new(key, value).is_none() {
+            return InsertOutcome::Rejected;
+        }
+        InsertOutcome::Inserted
+    }
+
+    /// Insert `value` only if there is no entry for `key`, returning `true` if there already was one.
+    ///
+    /// An existing entry is marked as visited and keeps its value. This returns `false` when `key`
+    /// was absent, even if the new entry couldn't be inserted because eviction is blocked.
+    pub fn contains_or_insert(&mut self, key: K, value: V) -> bool {
+        if self.access(&key).is_some() {
+            return true;
+        }
+        self.insert_new(key, value);
+        false
+    }
+
+    /// Apply `update` to the value mapped to by `key`, or insert the result of `insert` if there is
+    /// none, returning a reference to the value.
+    ///
+    /// An existing entry is marked as visited. This returns `None` if the new value couldn't be
+    /// inserted because eviction is blocked; `insert` isn't called when the evict condition already
+    /// rules out every entry.
+    pub fn update_or_insert_with<F, G>(&mut self, key: K, update: F, insert: G) -> Option<&V>
+    where
+        F: FnOnce(&mut V),
+        G: FnOnce() -> V,
+    {
+        let node_ = match self.access(&key) {
+            Some(mut node_) => {
+                update(unsafe { &mut node_.as_mut().value });
+                node_
+            }
+            None if !self.may_make_room() => return None,
+            None => self.insert_new(key, insert())?,
+        };
+        Some(unsafe { &(*node_.as_ptr()).value })
+    }
+
+    /// Get a reference to the value mapped to by `key`, replacing it with the result of `refresh`
+    /// if `is_stale` says it is out of date, or inserting it if there is none.
+    ///
+    /// A stale value counts as a miss. Like [`get_or_insert_with`](Self::get_or_insert_with),
+    /// this returns `None` if the value couldn't be stored.
+    pub fn get_or_refresh<P, F>(&mut self, key: K, is_stale: P, refresh: F) -> Option<&V>
+    where
+        P: FnOnce(&V) -> bool,
+        F: FnOnce() -> V,
+    {
+        let stale = match self.map.get(&key) {
+            Some(node) => is_stale(&node.value),
+            None => return self.get_or_insert_with(key, refresh),
+        };
+        if !stale {
+            return self.get(&key);
+        }
+        self.record_lookup(false);
+        self.insert_result(key.clone(), refresh());
+        self.map.get(&key).map(|node| &node.value)
+    }
+
+    /// Apply `f` to the value mapped to by `key` in place, marking the entry as visited.
+    ///
+    /// This returns `false` without inserting anything if there is no such entry.
+    pub fn update<Q, F: FnOnce(&mut V)>(&mut self, key: &Q, f: F) -> bool
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        self.get_mut(key).map(f).is_some()
+    }
+
+    /// Replace the value mapped to by `key` with `new`, only if it is currently equal to `expected`.
+    ///
+    /// This returns `true` and marks the entry as visited if the value was replaced. In a weighted
+    /// cache, a new value heavier than the maximum weight can't be stored: the entry is removed and
+    /// this returns `false`.
+    pub fn compare_and_update<Q>(&mut self, key: &Q, expected: &V, new: V) -> bool
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+        V: PartialEq,
+    {
+        let Some(node_) = self.map.get_mut(key) else {
+            return false;
+        };
+        if node_.value != *expected {
+            return false;
+        }
+        let node_ = NonNull::from(node_.as_mut());
+        if !self.replace_value(node_, new) {
+            return false;
+        }
+        self.touch(node_);
+        self.trim_weight();
+        true
+    }
+
+    /// Map `key` to `value` without evicting anything, even if the cache is full.
+    ///
+    /// This lets [`len`](Self::len) (or the weight of a weighted cache) temporarily exceed the
+    /// capacity, until the next insertion evicts entries or [`trim_to_capacity`](Self::trim_to_capacity)
+    /// is called. An existing entry is updated in place. A disabled cache still retains nothing.
+    pub fn insert_over_capacity(&mut self, key: K, value: V) {
+        let weight = self.weigh(&key, &value);
+        if let Some(node_) = self.map.get_mut(&key) {
+            node_.value = value;
+            self.weight = self.weight - node_.weight + weight;
+            node_.weight = weight;
+            let node_ = NonNull::from(node_.as_mut());
+            self.touch(node_);
+        } else if self.capacity > 0 {
+            self.link_new(key, value, weight);
+        }
+    }
+
+    /// Run one eviction step, as an insertion into a full cache would, returning `true` if an entry
+    /// was removed.
+    ///
+    /// The victim is chosen by the eviction policy, which may clear visited flags on its way, and
+    /// passed to the eviction listener. The evict condition is honored: this returns `false` when
+    /// the cache is empty or when no entry may be evicted.
+    pub fn evict_one(&mut self) -> bool {
+        self.len > 0 && self.evict()
+    }
+
+    /// Evict one entry, reporting what the eviction policy had to do to find it.
+    ///
+    /// The evicted entry is returned instead of being passed to the eviction listener. If nothing
+    /// can be evicted, every entry counts as scanned. This walks the whole list to detect the
+    /// cleared flags, so it is meant for tests and diagnostics.
+    pub fn evict_with_report(&mut self) -> EvictReport<K, V> {
+        let visited: Vec<_> = self
+            .map
+            .values()
+            .filter(|node| node.visited)
+            .map(|node| NonNull::from(node.as_ref()))
+            .collect();
+        let start = match self.policy {
+            PolicyImpl::Lru(_) => self.hand_origin(),
+            _ => self.hand.or(self.hand_origin()),
+        };
+        let victim = if self.len > 0 {
+            self.select_victim()
+        } else {
+            None
+        };
+        let mut wrapped = false;
+        let nodes_scanned = match victim {
+            Some(victim) => {
+                let mut node = start;
+                let mut scanned = 1;
+                while node.is_some_and(|node_| node_ != victim) && scanned < self.len {
+                    node = node
+                        .and_then(|node_| self.hand_step(node_))
+                        .or(self.hand_origin());
+                    scanned += 1;
+                }
+                // SIEVE only evicts a visited entry within the scan budget after a full lap
+                wrapped = matches!(self.policy, PolicyImpl::Sieve(_))
+                    && scanned <= self.max_scan
+                    && visited.contains(&victim);
+                if wrapped {
+                    scanned + self.len
+                } else {
+                    scanned
+                }
+            }
+            None => self.len,
+        };
+        let flags_cleared = visited
+            .into_iter()
+            .filter(|&node_| {
+                (Some(node_) != victim || wrapped) && !unsafe { node_.as_ref() }.visited
+            })
+            .count();
+        let evicted = victim
+            .and_then(|node_| self.unlink_victim(node_))
+            .map(|node| {
+                let node = *node;
+                (node.key, node.value)
+            });
+        EvictReport {
+            evicted,
+            nodes_scanned,
+            flags_cleared,
+        }
+    }
+
+    /// Evict entries until the cache fits in its capacity again, returning them.
+    ///
+    /// The evicted entries are returned instead of being passed to the eviction listener. Fewer
+    /// entries are evicted if the evict condition prevents it.
+    pub fn trim_to_capacity(&mut self) -> Vec<(K, V)> {
+        let mut evicted = Vec::new();
+        while self.len > self.capacity || self.weight > self.max_weight {
+            let Some(node) = self.evict_entry() else {
+                break;
+            };
+            let node = *node;
+            evicted.push((node.key, node.value));
+        }
+        evicted
+    }
+
+    /// Map `key` to `value` in the cache, returning the entry evicted to make room, if any.
+    ///
+    /// If the entry can't be inserted because eviction is blocked, `key` and `value` are handed
+    /// back. When several entries have to be evicted from a weighted cache, only the first one is
+    /// returned and the others are passed to the eviction listener.
+    #[allow(clippy::type_complexity)]
+    pub fn insert_full(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<(InsertOutcome, Option<(K, V)>), (K, V)> {
+        if self.map.contains_key(&key) {
+            return Ok((self.insert_result(key, value), None));
+        }
+        let weight = self.weigh(&key, &value);
+        if self.capacity == 0 || weight > self.max_weight {
+            return Err((key, value));
+        }
+        let mut evicted = None;
+        while self.len >= self.capacity || self.weight + weight > self.max_weight {
+            if self.len >= self.capacity && self.grow() {
+                continue;
+            }
+            let Some(node) = self.evict_entry() else {
+                self.stats.insert_rejections += 1;
+                return Err((key, value));
+            };
+            if evicted.is_none() {
+                let node = *node;
+                evicted = Some((node.key, node.value));
+            } else {
+                self.notify_evicted(*node);
+            }
+        }
+        self.link_new(key, value, weight);
+        Ok((InsertOutcome::Inserted, evicted))
+    }
+
+    /// Get an immutable reference to the value mapped to by `key`, inserting the result of `f` if
+    /// there is none.
+    ///
+    /// An existing entry is marked as visited, and `f` is only called on a miss. This returns
+    /// `None` if a new entry was needed but no existing entry could be evicted.
+    ///
+    /// `f` can't call back into the cache while the entry is being inserted: the cache stays
+    /// mutably borrowed for the whole call, so such code is rejected at compile time. Through a
+    /// shared [`SyncSieveCache`], such a call is caught at run time in debug builds, see
+    /// [`SyncSieveCache::with_lock`].
+    ///
+    /// ```compile_fail,E0499
+    /// let mut cache = sieve_cache::SieveCache::new(2).unwrap();
+    /// cache.get_or_insert_with(1, || {
+    ///     cache.insert(2, 2);
+    ///     1
+    /// });
+    /// ```
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, f: F) -> Option<&V> {
+        self.get_or_insert_with_mut(key, f).map(|value| &*value)
+    }
+
+    /// Get a mutable reference to the value mapped to by `key`, inserting the result of `f` if
+    /// there is none.
+    ///
+    /// An existing entry is marked as visited, and `f` is only called on a miss. This returns
+    /// `None` if a new entry was needed but no existing entry could be evicted; `f` isn't called
+    /// when the evict condition already rules out every entry.
+    pub fn get_or_insert_with_mut<F: FnOnce() -> V>(&mut self, key: K, f: F) -> Option<&mut V> {
+        let mut node = match self.access(&key) {
+            Some(node_) => node_,
+            None if !self.may_make_room() => return None,
+            None => self.insert_new(key, f())?,
+        };
+        Some(unsafe { &mut node.as_mut().value })
+    }
+
+    /// Get the values mapped to by `keys`, computing the missing ones with `loader`.
+    ///
+    /// Found entries are marked as visited, and `loader` is called once for each missing key.
+    /// The result is aligned with `keys`, with `None` for keys whose value couldn't be inserted
+    /// because eviction is blocked, or was evicted again to make room for a later key in the
+    /// same batch.
+    pub fn get_many_or_insert_with<F: FnMut(&K) -> V>(
+        &mut self,
+        keys: Vec<K>,
+        mut loader: F,
+    ) -> Vec<Option<&V>> {
+        for key in &keys {
+            if self.access(key).is_none() && self.may_make_room() {
+                let value = loader(key);
+                self.insert_new(key.clone(), value);
+            }
+        }
+        // References are only taken once every insertion is done, so none of them can dangle
+        keys.iter()
+            .map(|key| self.map.get(key).map(|node| &node.value))
+            .collect()
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but also report whether inserting the
+    /// new entry evicted another one.
+    ///
+    /// The flag is always `false` on a hit, and this returns `(None, false)` if the entry couldn't
+    /// be inserted.
+    pub fn get_or_insert_reporting<F: FnOnce() -> V>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> (Option<&V>, bool) {
+        if let Some(node_) = self.access(&key) {
+            return (Some(unsafe { &(*node_.as_ptr()).value }), false);
+        }
+        if !self.may_make_room() {
+            return (None, false);
+        }
+        let evictions = self.stats.evictions;
+        match self.insert_new(key, f()) {
+            Some(node_) => (
+                Some(unsafe { &(*node_.as_ptr()).value }),
+                self.stats.evictions != evictions,
+            ),
+            None => (None, false),
+        }
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but also report whether a new entry
+    /// was inserted.
+    ///
+    /// On success, this returns the value along with `true` if it was just inserted, or `false` if
+    /// it was already cached.
+    pub fn get_or_insert_checked<F: FnOnce() -> V>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> Result<(&V, bool), CacheError> {
+        let (node, inserted) = match self.access(&key) {
+            Some(node_) => (node_, false),
+            None if !self.may_make_room() => return Err(CacheError::EvictionBlocked),
+            None => (
+                self.insert_new(key, f())
+                    .ok_or(CacheError::EvictionBlocked)?,
+                true,
+            ),
+        };
+        Ok((unsafe { &(*node.as_ptr()).value }, inserted))
+    }
+
+    /// Like [`get_or_insert_with`](Self::get_or_insert_with), but only converts `key` into an owned
+    /// `K` on a miss, when a new entry is actually inserted.
+    pub fn get_or_insert_with_ref<Q, F>(&mut self, key: &Q, f: F) -> Option<&V>
+    where
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        K: Borrow<Q>,
+        F: FnOnce() -> V,
+    {
+        self.entry_ref(key).or_insert_with(f).map(|value| &*value)
+    }
+
+    /// Get an entry for `key` without requiring an owned key.
+    ///
+    /// The key is only converted into an owned `K` (through `ToOwned`) if a new entry is actually
+    /// inserted, so hits on a `SieveCache<String, V>` probed with a `&str` don't allocate.
+    /// An occupied entry is marked as visited.
+    pub fn entry_ref<'a, 'b, Q>(&'a mut self, key: &'b Q) -> EntryRef<'a, 'b, K, Q, V, S>
+    where
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        K: Borrow<Q>,
+    {
+        match self.access(key) {
+            Some(node) => EntryRef::Occupied(OccupiedEntryRef {
+                node,
+                marker: PhantomData,
+            }),
+            None => EntryRef::Vacant(VacantEntryRef { cache: self, key }),
+        }
+    }
+
+    /// Insert `items` as if each of them had already been accessed once, to pre-load hot data.
+    ///
+    /// Unlike entries inserted with [`insert`](Self::insert), new entries start with their visited
+    /// flag set, so they survive the first eviction pass instead of being evicted before colder
+    /// entries. Existing entries are updated as with `insert`.
+    pub fn warm<I: IntoIterator<Item = (K, V)>>(&mut self, items: I) {
+        for (key, value) in items {
+            if self.map.contains_key(&key) {
+                self.insert_result(key, value);
+            } else if let Some(mut node_) = self.insert_new(key, value) {
+                unsafe { node_.as_mut().visited = true };
+            }
+        }
+    }
+
+    /// Insert `(key, value, visited)` triples so that they end up in the same head-to-tail order
+    /// as `items`, with their visited flags set as given.
+    ///
+    /// Each new entry is linked after the current tail, so existing entries stay newer than the
+    /// appended ones. An existing key has its value and visited flag replaced, keeping its position.
+    /// When the cache is full, an entry is evicted first; items that can't be inserted because
+    /// eviction is blocked, or that are heavier than the maximum weight, are dropped, along with
+    /// the entry they would have replaced. This is the primitive used to restore a snapshot.
+    pub fn append_in_order<I: IntoIterator<Item = (K, V, bool)>>(&mut self, items: I) {
+        for (key, value, visited) in items {
+            if let Some(node) = self.map.get_mut(&key) {
+                let mut node_ = NonNull::from(node.as_mut());
+                if self.replace_value(node_, value) {
+                    unsafe { node_.as_mut().visited = visited };
+                    self.trim_weight();
+                }
+                continue;
+            }
+            let weight = self.weigh(&key, &value);
+            if self.capacity == 0 || !self.make_room(weight) {
+                continue;
+            }
+            let mut node = Box::new(Node::new(key.clone(), value));
+            node.weight = weight;
+            let mut node_ = NonNull::from(node.as_ref());
+            self.map.insert(key, node);
+            self.add_node_tail(node_);
+            self.len += 1;
+            self.weight += weight;
+            self.stats.insertions += 1;
+            self.report_len();
+            self.policy.on_insert(node_);
+            unsafe { node_.as_mut().visited = visited };
+        }
+    }
+
+    /// Remove every entry, passing them to `sink` in eviction order, from the oldest to the newest.
+    ///
+    /// Each entry is unlinked before being passed to `sink`, so if `sink` panics, the entries
+    /// flushed so far are gone and the remaining ones are left in the cache, which stays usable.
+    /// The eviction listener isn't called.
+    pub fn flush_to<F: FnMut(K, V)>(&mut self, mut sink: F) {
+        while let Some(node_) = self.tail {
+            let node = *self.remove_entry_node(node_);
+            sink(node.key, node.value);
+        }
+    }
+
+    /// Remove and return the first entry matching `pred`, scanning from the head to the tail.
+    pub fn remove_matching_first<F: FnMut(&K, &V) -> bool>(
+        &mut self,
+        mut pred: F,
+    ) -> Option<(K, V)> {
+        let mut node = self.head;
+        while let Some(node_) = node {
+            let node_ref = unsafe { node_.as_ref() };
+            if pred(&node_ref.key, &node_ref.value) {
+                let node = *self.remove_entry_node(node_);
+                return Some((node.key, node.value));
+            }
+            node = node_ref.next;
+        }
+        None
+    }
+
+    /// Remove the entries whose value equals the value of an entry closer to the head.
+    ///
+    /// Only the newest entry of each group of equal values is kept. This returns the number of
+    /// removed entries. Values are compared pairwise, so this takes quadratic time.
+    pub fn dedup_values(&mut self) -> usize
+    where
+        V: PartialEq,
+    {
+        let mut kept: Vec<NonNull<Node<K, V>>> = Vec::new();
+        let mut removed = 0;
+        let mut node = self.head;
+        while let Some(node_) = node {
+            let node_ref = unsafe { node_.as_ref() };
+            node = node_ref.next;
+            if kept
+                .iter()
+                .any(|kept_| unsafe { kept_.as_ref() }.value == node_ref.value)
+            {
+                self.remove_entry_node(node_);
+                removed += 1;
+            } else {
+                kept.push(node_);
+            }
+        }
+        removed
+    }
+
+    /// Map the entry of `old` to `new` instead, keeping its value, visited flag and position.
+    ///
+    /// This returns `false` and changes nothing if there is no entry for `old`, or if there is
+    /// already one for `new`.
+    pub fn replace_key(&mut self, old: &K, new: K) -> bool {
+        if self.map.contains_key(&new) {
+            return false;
+        }
+        let Some(mut node) = self.map.remove(old) else {
+            return false;
+        };
+        node.key = new.clone();
+        self.map.insert(new, node);
+        true
+    }
+
+    /// Move the entry mapped to by `key` to the head of the list and mark it as visited,
+    /// returning `false` if there is no such entry.
+    ///
+    /// The entry is then treated as if it had just been inserted and accessed, which protects it
+    /// from the next evictions: the eviction policy is notified of the access, and the entry counts
+    /// as active for [`decay_visited`](Self::decay_visited). This isn't counted as a lookup.
+    pub fn promote<Q>(&mut self, key: &Q) -> bool
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let Some(node) = self.map.get_mut(key) else {
+            return false;
+        };
+        let node_ = NonNull::from(node.as_mut());
+        if self.hand == Some(node_) && self.head != Some(node_) {
+            self.hand = self.hand_step(node_);
+        }
+        self.touch(node_);
+        if self.head != Some(node_) {
+            self.remove_node(node_);
+            self.add_node(node_);
+        }
+        // Policies that ignore accesses, like FIFO, still get the entry protected
+        unsafe { (*node_.as_ptr()).visited = true };
+        true
+    }
+
+    /// Remove the cache entry mapped to by `key`.
+    ///
+    /// This method returns the value removed from the cache. If `key` did not map to any value,
+    /// then this returns `None`.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let node_ = NonNull::from(self.map.get(key)?.as_ref());
+        Some(self.remove_entry_node(node_).value)
+    }
+
+    /// Take the value mapped to by `key` out of the cache, typically to insert it again shortly.
+    ///
+    /// This is the same as [`remove`](Self::remove): the position of the entry isn't remembered,
+    /// so inserting the key again links it at the head like any new entry.
+    #[inline]
+    pub fn take<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.remove(key)
+    }
+
+    /// Remove the cache entry mapped to by `key`, on a hot path.
+    ///
+    /// This is equivalent to [`remove`](Self::remove), and is guaranteed to never update the
+    /// statistics nor call back into user code, even if `remove` starts doing so.
+    #[inline]
+    pub fn remove_fast<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let node_ = NonNull::from(self.map.get(key)?.as_ref());
+        Some(self.remove_entry_node(node_).value)
+    }
+
+    /// Keep only the entries for which `f` returns `true`, visiting them from head to tail.
+    pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+        self.retain_with_visited(|key, value, _| f(key, value));
+    }
+
+    /// Keep only the entries for which `f` returns `true`, visiting them from head to tail.
+    ///
+    /// `f` also receives the visited flag of each entry, so that cold entries can be pruned
+    /// while frequently accessed ones are kept. Flags are left unchanged.
+    pub fn retain_with_visited<F: FnMut(&K, &mut V, bool) -> bool>(&mut self, mut f: F) {
+        let mut node = self.head;
+        while let Some(mut node_) = node {
+            let node_ref = unsafe { node_.as_mut() };
+            node = node_ref.next;
+            if !f(&node_ref.key, &mut node_ref.value, node_ref.visited) {
+                self.remove_entry_node(node_);
+            }
+        }
+    }
+
+    /// Clear the visited flag of the entries that weren't accessed since the previous call.
+    ///
+    /// Calling this periodically makes the protection of an entry expire unless it keeps being
+    /// accessed, which approximates access frequency over time. Entries accessed since the
+    /// previous call keep their flag, and count as not accessed for the next call.
+    pub fn decay_visited(&mut self) {
+        let mut node = self.head;
+        while let Some(mut node_) = node {
+            let node_ref = unsafe { node_.as_mut() };
+            if !node_ref.recent {
+                node_ref.visited = false;
+            }
+            node_ref.recent = false;
+            node = node_ref.next;
+        }
+    }
+
+    /// Apply `f` to every cached value in place, without marking entries as visited.
+    ///
+    /// This is meant to reduce memory usage, e.g. with `String::shrink_to_fit`; weights aren't
+    /// recomputed.
+    pub fn compact_values<F: FnMut(&mut V)>(&mut self, mut f: F) {
+        let mut node = self.head;
+        while let Some(mut node_) = node {
+            let node_ref = unsafe { node_.as_mut() };
+            f(&mut node_ref.value);
+            node = node_ref.next;
+        }
+    }
+
+    /// Return the entries that the next `n` evictions would remove, in eviction order.
+    ///
+    /// This simulates successive SIEVE evictions without modifying the cache: visited flags
+    /// that these evictions would clear are only cleared in the simulation, and the evict
+    /// condition is honored. Fewer than `n` entries are returned if the cache holds fewer
+    /// entries, or if eviction would get blocked before reaching `n`.
+    ///
+    /// Custom eviction policies can't be simulated, so this always returns an empty `Vec` for them.
+    pub fn peek_victims(&self, n: usize) -> Vec<(&K, &V)> {
+        let (honor_visited, use_hand) = match self.policy {
+            PolicyImpl::Sieve(_) => (true, true),
+            PolicyImpl::Fifo(_) => (false, true),
+            PolicyImpl::Lru(_) => (false, false),
+            PolicyImpl::Custom(_) => return Vec::new(),
+        };
+        let mut victims = Vec::with_capacity(n.min(self.len));
+        let mut evicted = HashSet::new();
+        let mut cleared = HashSet::new();
+        let skip_evicted = |mut node: Option<NonNull<Node<K, V>>>, evicted: &HashSet<_>| {
+            while let Some(node_) = node {
+                if !evicted.contains(&node_) {
+                    break;
+                }
+                node = self.hand_step(node_);
+            }
+            node
+        };
+        let mut hand = self.hand.filter(|_| use_hand);
+        let mut len = self.len;
+        'evictions: while victims.len() < n {
+            let mut node = hand.or_else(|| skip_evicted(self.hand_origin(), &evicted));
+            let mut scanned = 0;
+            // Like SIEVE, take a second lap only if the first one cleared a flag
+            let mut cleared_any = false;
+            let victim = loop {
+                let Some(node_) = node else {
+                    break 'evictions;
+                };
+                let laps = if cleared_any { 2 } else { 1 };
+                if scanned >= laps * len {
+                    break 'evictions;
+                }
+                scanned += 1;
+                let node_ref = unsafe { node_.as_ref() };
+                let flag = node_ref.visited && !(honor_visited && cleared.contains(&node_));
+                let visited = flag && honor_visited && scanned <= self.max_scan;
+                if !visited
+                    && self
+                        .evict_condition
+                        .is_none_or(|cond| cond.allows(&node_ref.key, &node_ref.value, flag))
+                {
+                    break node_ref;
+                }
+                if flag && honor_visited {
+                    cleared_any = true;
+                }
+                cleared.insert(node_);
+                node = skip_evicted(self.hand_step(node_), &evicted)
+                    .or_else(|| skip_evicted(self.hand_origin(), &evicted));
+            };
+            victims.push((&victim.key, &victim.value));
+            evicted.insert(NonNull::from(victim));
+            hand =
+                skip_evicted(self.hand_step(NonNull::from(victim)), &evicted).filter(|_| use_hand);
+            len -= 1;
+        }
+        victims
+    }
+
+    /// Return the entry at the head of the list, i.e. the most recently inserted one.
+    ///
+    /// This doesn't mark the entry as visited.
+    pub fn front(&self) -> Option<(&K, &V)> {
+        self.head.map(|node_| {
+            let node_ref = unsafe { node_.as_ref() };
+            (&node_ref.key, &node_ref.value)
+        })
+    }
+
+    /// Return the entry at the tail of the list, i.e. the oldest one, where eviction starts.
+    ///
+    /// This doesn't mark the entry as visited.
+    pub fn back(&self) -> Option<(&K, &V)> {
+        self.tail.map(|node_| {
+            let node_ref = unsafe { node_.as_ref() };
+            (&node_ref.key, &node_ref.value)
+        })
+    }
+
+    /// Alias of [`front`](Self::front).
+    #[inline]
+    pub fn newest(&self) -> Option<(&K, &V)> {
+        self.front()
+    }
+
+    /// Alias of [`back`](Self::back).
+    #[inline]
+    pub fn oldest(&self) -> Option<(&K, &V)> {
+        self.back()
+    }
+
+    /// Return the first entry whose visited flag is unset, in the order the SIEVE hand scans entries.
+    ///
+    /// The scan starts at the hand (or the tail), moves toward the head, and wraps around, unless
+    /// the scan direction was reversed. This is the entry that SIEVE would evict without clearing
+    /// any flag, ignoring the evict condition.
+    pub fn first_unvisited(&self) -> Option<(&K, &V)> {
+        let mut node = self.hand.or(self.hand_origin());
+        for _ in 0..self.len {
+            let node_ref = unsafe { node?.as_ref() };
+            if !node_ref.visited {
+                return Some((&node_ref.key, &node_ref.value));
+            }
+            node = self.hand_step(node?).or(self.hand_origin());
+        }
+        None
+    }
+
+    /// Return the position of the entry mapped to by `key` in the order the hand scans entries,
+    /// or `None` if there is no such entry.
+    ///
+    /// The entry the next eviction looks at first has rank 0. Visited flags are ignored, so a
+    /// low rank doesn't mean that the entry is the next one to be evicted.
+    pub fn rank<Q>(&self, key: &Q) -> Option<usize>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let target = NonNull::from(self.map.get(key)?.as_ref());
+        let mut node = match self.policy {
+            PolicyImpl::Lru(_) => self.hand_origin(),
+            _ => self.hand.or(self.hand_origin()),
+        };
+        for rank in 0..self.len {
+            let node_ = node?;
+            if node_ == target {
+                return Some(rank);
+            }
+            node = self.hand_step(node_).or(self.hand_origin());
+        }
+        unreachable!("the entry is not in the list")
+    }
+
+    /// Return the visited flag, rank and weight of the entry mapped to by `key` in a single call.
+    ///
+    /// This doesn't mark the entry as visited. The cache doesn't track insertion times, so no age
+    /// is reported.
+    pub fn metadata<Q>(&self, key: &Q) -> Option<EntryMeta>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let node = self.map.get(key)?;
+        Some(EntryMeta {
+            visited: node.visited,
+            rank: self.rank(key)?,
+            weight: node.weight,
+        })
+    }
+
+    /// Return references to every entry in eviction order, from the oldest entry to the newest.
+    ///
+    /// No entry is marked as visited, and neither keys nor values need to be `Clone`.
+    pub fn as_refs(&self) -> Vec<(&K, &V)> {
+        self.iter().rev().collect()
+    }
+
+    /// Return an iterator over the entries, from the most recently inserted to the oldest.
+    ///
+    /// Iterating doesn't mark entries as visited. Use `iter().rev()` to walk from the oldest entry.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            front: self.head,
+            back: self.tail,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return an iterator over the keys, from the most recently inserted entry to the oldest.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Return an iterator over the values, from the most recently inserted entry to the oldest.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    /// Return up to `n` entries, picked at regular intervals from the newest entry to the oldest.
+    ///
+    /// This gives a cheap overview of the contents of a large cache. The sample is evenly spread
+    /// over the eviction order but isn't random, and no entry is marked as visited.
+    pub fn sample(&self, n: usize) -> Vec<(&K, &V)> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let stride = (self.len / n).max(1);
+        self.iter().step_by(stride).take(n).collect()
+    }
+
+    /// Return up to `n` keys of visited entries, from the newest entry to the oldest.
+    ///
+    /// Together with [`sample_cold`](Self::sample_cold), this gives a cheap view of the hot/cold
+    /// split of the cache. No flag is changed.
+    pub fn sample_hot(&self, n: usize) -> Vec<&K> {
+        self.sample_by_visited(n, true)
+    }
+
+    /// Return up to `n` keys of entries that are not visited, from the newest entry to the oldest.
+    pub fn sample_cold(&self, n: usize) -> Vec<&K> {
+        self.sample_by_visited(n, false)
+    }
+
+    fn sample_by_visited(&self, n: usize, visited: bool) -> Vec<&K> {
+        let mut keys = Vec::new();
+        let mut node = self.head;
+        while let Some(node_) = node {
+            if keys.len() >= n {
+                break;
+            }
+            let node_ref = unsafe { node_.as_ref() };
+            if node_ref.visited == visited {
+                keys.push(&node_ref.key);
+            }
+            node = node_ref.next;
+        }
+        keys
+    }
+
+    /// Return a compact description of the eviction state, for snapshot tests.
+    ///
+    /// Keys are listed from the head to the tail, with visited ones followed by `*`, and the key
+    /// the hand points at is appended, as in `"a* -> b -> c* (hand@b)"`.
+    pub fn order_string(&self) -> String
+    where
+        K: std::fmt::Display,
+    {
+        let mut order = Vec::with_capacity(self.len);
+        let mut node = self.head;
+        while let Some(node_) = node {
+            let node_ref = unsafe { node_.as_ref() };
+            let mark = if node_ref.visited { "*" } else { "" };
+            order.push(format!("{}{}", node_ref.key, mark));
+            node = node_ref.next;
+        }
+        let mut order = order.join(" -> ");
+        if let Some(hand) = self.hand {
+            order += &format!(" (hand@{})", unsafe { &hand.as_ref().key });
+        }
+        order
+    }
+
+    /// Return copies of the `n` most recently inserted entries, from the newest one.
+    ///
+    /// This bounds the cost of persisting a large cache; restore the entries with
+    /// [`from_pairs`](SieveCache::from_pairs).
+    pub fn serialize_top(&self, n: usize) -> Vec<(K, V)>
+    where
+        V: Clone,
+    {
+        self.iter()
+            .take(n)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Consume the cache, yielding its entries with their visited flags from head to tail.
+    ///
+    /// Feeding the result to [`append_in_order`](Self::append_in_order) on an empty cache
+    /// rebuilds the same eviction order and flags.
+    pub fn into_iter_with_meta(self) -> impl Iterator<Item = (K, V, bool)> {
+        let mut keys = Vec::with_capacity(self.len);
+        let mut node = self.head;
+        while let Some(node_) = node {
+            let node_ref = unsafe { node_.as_ref() };
+            keys.push(node_ref.key.clone());
+            node = node_ref.next;
+        }
+        // The map owns the nodes, so taking each node out of it hands over ownership exactly once
+        let mut map = self.map;
+        keys.into_iter().map(move |key| {
+            let node = map.remove(&key).expect("listed keys are in the map");
+            (node.key, node.value, node.visited)
+        })
+    }
+
+    /// Consume the cache, returning its entries sorted by key.
+    ///
+    /// The order has nothing to do with the eviction order; see [`iter`](Self::iter) for that.
+    pub fn into_sorted_vec(self) -> Vec<(K, V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<_> = self
+            .map
+            .into_values()
+            .map(|node| (node.key, node.value))
+            .collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Verify the internal invariants of the cache, panicking if any of them is broken.
+    ///
+    /// This walks the whole list, so it is only meant for tests and fuzzing.
+    pub fn check_consistency(&self) {
+        assert_eq!(self.len, self.map.len(), "len doesn't match the map");
+        // `len` and `weight` may exceed their bounds after `insert_over_capacity`
+        let weight: usize = self.map.values().map(|node| node.weight).sum();
+        assert_eq!(self.weight, weight, "weight doesn't match the entries");
+        let mut count = 0;
+        let mut hand_found = self.hand.is_none();
+        let mut prev = None;
+        let mut node = self.head;
+        while let Some(node_) = node {
+            let node_ref = unsafe { node_.as_ref() };
+            assert_eq!(node_ref.prev, prev, "broken prev link");
+            let mapped = self
+                .map
+                .get(&node_ref.key)
+                .expect("node missing from the map");
+            assert_eq!(
+                NonNull::from(mapped.as_ref()),
+                node_,
+                "stale node in the list"
+            );
+            hand_found |= self.hand == Some(node_);
+            count += 1;
+            assert!(count <= self.len, "the list has a cycle or too many nodes");
+            prev = node;
+            node = node_ref.next;
+        }
+        assert_eq!(self.tail, prev, "tail is not the last node");
+        assert_eq!(count, self.len, "the list and the map disagree");
+        assert!(hand_found, "the hand points outside the list");
+    }
+
+    /// Return the direction of the sweeps, which LRU always does from the tail.
+    #[inline]
+    fn sweep_direction(&self) -> ScanDirection {
+        match self.policy {
+            PolicyImpl::Lru(_) => ScanDirection::TailToHead,
+            _ => self.scan_direction,
+        }
+    }
+
+    /// Return the entry the hand moves to after `node_`, or `None` when it has to wrap around.
+    #[inline]
+    fn hand_step(&self, node_: NonNull<Node<K, V>>) -> Option<NonNull<Node<K, V>>> {
+        let node_ref = unsafe { node_.as_ref() };
+        match self.sweep_direction() {
+            ScanDirection::TailToHead => node_ref.prev,
+            ScanDirection::HeadToTail => node_ref.next,
+        }
+    }
+
+    /// Return the entry the hand starts from, or wraps around to.
+    #[inline]
+    fn hand_origin(&self) -> Option<NonNull<Node<K, V>>> {
+        match self.sweep_direction() {
+            ScanDirection::TailToHead => self.tail,
+            ScanDirection::HeadToTail => self.head,
+        }
+    }
+
+    /// Unlink `node_` from the list and the map, moving the hand past it if needed.
+    fn remove_entry_node(&mut self, node_: NonNull<Node<K, V>>) -> Box<Node<K, V>> {
+        if self.hand == Some(node_) {
+            self.hand = self.hand_step(node_);
+        }
+        self.remove_node(node_);
+        let node = self
+            .map
+            .remove(unsafe { &node_.as_ref().key })
+            .expect("node missing from the map");
+        debug_assert!(self.len > 0);
+        self.len -= 1;
+        self.weight -= node.weight;
+        self.report_len();
+        node
+    }
+
+    /// Look up `key`, recording a hit or a miss and notifying the policy of the access.
+    fn access<Q>(&mut self, key: &Q) -> Option<NonNull<Node<K, V>>>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        let node = self
+            .map
+            .get_mut(key)
+            .map(|node| NonNull::from(node.as_mut()));
+        self.record_lookup(node.is_some());
+        let node_ = node?;
+        self.touch(node_);
+        Some(node_)
+    }
+
+    /// Count a hit or a miss, only once every `stats_sampling` lookups.
+    #[inline]
+    fn record_lookup(&mut self, hit: bool) {
+        self.stats_tick += 1;
+        if self.stats_tick < self.stats_sampling {
+            return;
+        }
+        self.stats_tick = 0;
+        if hit {
+            self.stats.hits += self.stats_sampling;
+        } else {
+            self.stats.misses += self.stats_sampling;
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.lookups(hit, self.stats_sampling);
+        }
+    }
+
+    /// Set the `len` gauge of the metrics recorder, if there is one.
+    #[inline]
+    fn report_len(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.len(self.len);
+        }
+    }
+
+    /// Notify the policy of an access to `node_`, moving it to the head under [`Policy::Lru`].
+    fn touch(&mut self, node_: NonNull<Node<K, V>>) {
+        unsafe { (*node_.as_ptr()).recent = true };
+        self.policy.on_access(node_);
+        if matches!(self.policy, PolicyImpl::Lru(_)) && self.head != Some(node_) {
+            self.remove_node(node_);
+            self.add_node(node_);
+        }
+    }
+
+    fn insert_new(&mut self, key: K, value: V) -> Option<NonNull<Node<K, V>>> {
+        if self.capacity == 0 {
+            // The cache is disabled
+            return None;
+        }
+        let weight = self.weigh(&key, &value);
+        if !self.make_room(weight) {
+            return None;
+        }
+        debug_assert!(self.len < self.capacity);
+        Some(self.link_new(key, value, weight))
+    }
+
+    /// Link a new entry at the head, whether or not there is room for it.
+    fn link_new(&mut self, key: K, value: V, weight: usize) -> NonNull<Node<K, V>> {
+        let mut node = Box::new(Node::new(key.clone(), value));
+        node.weight = weight;
+        let node_ = NonNull::from(node.as_ref());
+        // The node is only linked once the map owns it, and the policy is only notified once the
+        // cache is consistent, so that a panicking `Hash` impl or hook can't corrupt the cache.
+        self.map.insert(key, node);
+        self.add_node(node_);
+        self.len += 1;
+        self.weight += weight;
+        self.stats.insertions += 1;
+        self.report_len();
+        self.policy.on_insert(node_);
+        node_
+    }
+
+    fn weigh(&self, key: &K, value: &V) -> usize {
+        self.weigher.map_or(0, |weigher| weigher(key, value).max(1))
+    }
+
+    /// Return `false` if a new entry certainly can't be inserted, without evicting anything.
+    ///
+    /// This is used to avoid computing values that would be dropped anyway, and counts the
+    /// insertion as rejected when returning `false` because of the evict condition. Only the room
+    /// already used (entries, or weight in a weighted cache) and the evict condition are
+    /// considered, since the weight of the new entry isn't known yet, so a `true` doesn't guarantee
+    /// that the insertion succeeds.
+    fn may_make_room(&mut self) -> bool {
+        if self.capacity == 0 {
+            return false;
+        }
+        if !self.is_full() || self.growth.is_some_and(|(max, _)| self.capacity < max) {
+            return true;
+        }
+        let Some(cond) = self.evict_condition else {
+            return true;
+        };
+        // Sweeping may clear visited flags before the condition is checked again
+        let possible = self.map.values().any(|node| {
+            cond.allows(&node.key, &node.value, node.visited)
+                || cond.allows(&node.key, &node.value, false)
+        });
+        if !possible {
+            self.stats.insert_rejections += 1;
+        }
+        possible
+    }
+
+    /// Evict entries until a new entry weighing `weight` fits, returning `false` if it can't.
+    fn make_room(&mut self, weight: usize) -> bool {
+        if weight > self.max_weight {
+            return false;
+        }
+        while self.len >= self.capacity || self.weight + weight > self.max_weight {
+            if self.len >= self.capacity && self.grow() {
+                continue;
+            }
+            if !self.evict() {
+                self.stats.insert_rejections += 1;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Grow the capacity according to the growth policy, returning `false` if it can't grow.
+    fn grow(&mut self) -> bool {
+        let Some((max, factor)) = self.growth else {
+            return false;
+        };
+        if self.capacity >= max {
+            return false;
+        }
+        let grown = ((self.capacity as f64 * factor).ceil() as usize).clamp(self.capacity + 1, max);
+        self.map.reserve(grown - self.len);
+        self.capacity = grown;
+        true
+    }
+
+    /// Replace the value of `node_`, an entry of the cache, updating its weight.
+    ///
+    /// A value heavier than `max_weight` can't be stored, so the entry is removed instead and this
+    /// returns `false`. The caller is left to evict entries until the total weight fits again.
+    fn replace_value(&mut self, node_: NonNull<Node<K, V>>, value: V) -> bool {
+        let node_ref = unsafe { &mut *node_.as_ptr() };
+        let weight = self.weigh(&node_ref.key, &value);
+        if weight > self.max_weight {
+            self.remove_entry_node(node_);
+            return false;
+        }
+        node_ref.value = value;
+        self.weight = self.weight - node_ref.weight + weight;
+        node_ref.weight = weight;
+        true
+    }
+
+    /// Evict entries until the total weight fits again after a value got heavier.
+    fn trim_weight(&mut self) {
+        while self.weight > self.max_weight && self.evict() {}
+    }
+
+    fn add_node(&mut self, mut node: NonNull<Node<K, V>>) {
+        // Linking a node that is still linked would make it point to itself
+        debug_assert!(self.head != Some(node) && self.tail != Some(node));
+        unsafe {
+            node.as_mut().next = self.head;
+            node.as_mut().prev = None;
+            if let Some(mut head) = self.head {
+                head.as_mut().prev = Some(node);
+            }
+        }
+        self.head = Some(node);
+        if self.tail.is_none() {
+            self.tail = self.head;
+        }
+    }
+
+    fn add_node_tail(&mut self, mut node: NonNull<Node<K, V>>) {
+        debug_assert!(self.head != Some(node) && self.tail != Some(node));
+        unsafe {
+            node.as_mut().prev = self.tail;
+            node.as_mut().next = None;
+            if let Some(mut tail) = self.tail {
+                tail.as_mut().next = Some(node);
+            }
+        }
+        self.tail = Some(node);
+        if self.head.is_none() {
+            self.head = self.tail;
+        }
+    }
+
+    fn remove_node(&mut self, node: NonNull<Node<K, V>>) {
+        unsafe {
+            debug_assert!(
+                node.as_ref().prev != Some(node) && node.as_ref().next != Some(node),
+                "a node links to itself"
+            );
+            if let Some(mut prev) = node.as_ref().prev {
+                prev.as_mut().next = node.as_ref().next;
+            } else {
+                self.head = node.as_ref().next;
+            }
+            if let Some(mut next) = node.as_ref().next {
+                next.as_mut().prev = node.as_ref().prev;
+            } else {
+                self.tail = node.as_ref().prev;
+            }
+        }
+    }
+
+    fn evict(&mut self) -> bool {
+        if self.len == 0 {
+            return true;
+        }
+        let Some(node) = self.evict_entry() else {
+            return false;
+        };
+        self.notify_evicted(*node);
+        true
+    }
+
+    fn notify_evicted(&mut self, node: Node<K, V>) {
+        if let Some(listener) = &mut self.evict_listener {
+            listener(node.key, node.value);
+        }
+    }
+
+    /// Evict the entry selected by the policy without notifying the listener, returning it.
+    fn evict_entry(&mut self) -> Option<Box<Node<K, V>>> {
+        let node_ = self.select_victim()?;
+        self.unlink_victim(node_)
+    }
+
+    /// Ask the policy for the entry to evict, without evicting it.
+    fn select_victim(&mut self) -> Option<NonNull<Node<K, V>>> {
+        let list = NodeList {
+            head: self.head,
+            tail: self.tail,
+            hand: self.hand,
+            len: self.len,
+            evict_condition: self.evict_condition,
+            max_scan: self.max_scan,
+            direction: self.scan_direction,
+            vetoes: Cell::new(0),
+            marker: PhantomData,
+        };
+        let victim = self.policy.select_victim(&list);
+        self.stats.condition_vetoes += list.vetoes.get();
+        let node_ = victim?;
+        let key = unsafe { &node_.as_ref().key };
+        assert!(
+            self.map
+                .get(key)
+                .is_some_and(|node| NonNull::from(node.as_ref()) == node_),
+            "the eviction policy selected an entry that isn't in the cache"
+        );
+        Some(node_)
+    }
+
+    fn unlink_victim(&mut self, node_: NonNull<Node<K, V>>) -> Option<Box<Node<K, V>>> {
+        self.hand = self.hand_step(node_);
+        self.remove_node(node_);
+        let node = unsafe {
+            self.weight -= node_.as_ref().weight;
+            self.map.remove(&node_.as_ref().key)
+        };
+        debug_assert!(self.len > 0);
+        self.len -= 1;
+        self.stats.evictions += 1;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.eviction();
+            metrics.len(self.len);
+        }
+        node
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> SieveCache<K, V, S> {
+    /// Get a reference to the value mapped to by `key`, awaiting the fallible loader `f` on a miss.
+    ///
+    /// If the loader fails, its error is returned and nothing is inserted. If the value can't be
+    /// inserted because eviction is blocked, [`CacheError::EvictionBlocked`] is converted into
+    /// `E` and returned; the loader isn't awaited when the evict condition already rules out every
+    /// entry.
+    pub async fn try_get_or_insert_with_async<F, Fut, E>(&mut self, key: K, f: F) -> Result<&V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<V, E>>,
+        E: From<CacheError>,
+    {
+        if let Some(node_) = self.access(&key) {
+            return Ok(unsafe { &(*node_.as_ptr()).value });
+        }
+        if !self.may_make_room() {
+            return Err(CacheError::EvictionBlocked.into());
+        }
+        let value = f().await?;
+        let node_ = self
+            .insert_new(key, value)
+            .ok_or(CacheError::EvictionBlocked)?;
+        Ok(unsafe { &(*node_.as_ptr()).value })
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> SieveCache<K, V> {
+    /// Create a cache from keys saved by [`serialize_keys`](Self::serialize_keys), loading each
+    /// value with `loader`.
+    ///
+    /// Keys are inserted in the given order, so the eviction order is restored. Keys for which
+    /// `loader` returns `None` are skipped. Visited flags are not persisted and start unset.
+    pub fn from_keys_with_loader<I, F>(
+        capacity: usize,
+        keys: I,
+        mut loader: F,
+    ) -> Result<Self, &'static str>
+    where
+        I: IntoIterator<Item = K>,
+        F: FnMut(&K) -> Option<V>,
+    {
+        let mut cache = Self::new(capacity)?;
+        for key in keys {
+            if let Some(value) = loader(&key) {
+                cache.insert(key, value);
+            }
+        }
+        Ok(cache)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> SieveCache<K, V, S> {
+    /// Return the cached keys in eviction order, from the oldest entry to the newest one.
+    ///
+    /// This is a cheap way to persist the topology of the cache without its values, to warm it up
+    /// again with [`from_keys_with_loader`](SieveCache::from_keys_with_loader). The keys come as a
+    /// plain `Vec`, which serde or any other format can store as is.
+    pub fn serialize_keys(&self) -> Vec<K> {
+        let mut keys = Vec::with_capacity(self.len);
+        let mut node = self.tail;
+        while let Some(node_) = node {
+            let node_ref = unsafe { node_.as_ref() };
+            keys.push(node_ref.key.clone());
+            node = node_ref.prev;
+        }
+        keys
+    }
+}
+
+impl<K, V, S> Clone for SieveCache<K, V, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    fn clone(&self) -> Self {
+        let mut cache = Self {
+            map: HashMap::with_capacity_and_hasher(self.map.capacity(), self.hasher().clone()),
+            head: None,
+            tail: None,
+            hand: None,
+            capacity: self.capacity,
+            growth: self.growth,
+            len: 0,
+            evict_condition: self.evict_condition,
+            policy: self.policy.clone(),
+            stats: self.stats,
+            stats_sampling: self.stats_sampling,
+            stats_tick: self.stats_tick,
+            compaction_threshold: self.compaction_threshold,
+            max_scan: self.max_scan,
+            scan_direction: self.scan_direction,
+            weigher: self.weigher,
+            max_weight: self.max_weight,
+            weight: 0,
+            evict_listener: None,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.clone(),
+        };
+        cache.relink_clones_of(self, Vec::new());
+        cache
+    }
+
+    /// Turn `self` into a copy of `source`, including visited flags, eviction order and the hand.
+    ///
+    /// The nodes of `self` are reused instead of being reallocated. The map is rebuilt with a clone
+    /// of the hasher of `source`, as [`clone`](Clone::clone) would do.
+    fn clone_from(&mut self, source: &Self) {
+        let map = HashMap::with_capacity_and_hasher(
+            self.map.capacity().max(source.len),
+            source.hasher().clone(),
+        );
+        let spare = std::mem::replace(&mut self.map, map)
+            .into_values()
+            .collect();
+        self.head = None;
+        self.tail = None;
+        self.hand = None;
+        self.len = 0;
+        self.capacity = source.capacity;
+        self.growth = source.growth;
+        self.evict_condition = source.evict_condition;
+        self.policy = source.policy.clone();
+        self.stats = source.stats;
+        self.stats_sampling = source.stats_sampling;
+        self.stats_tick = source.stats_tick;
+        self.compaction_threshold = source.compaction_threshold;
+        self.max_scan = source.max_scan;
+        self.scan_direction = source.scan_direction;
+        self.weigher = source.weigher;
+        self.max_weight = source.max_weight;
+        self.weight = 0;
+        self.evict_listener = None;
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics = source.metrics.clone();
+        }
+        self.relink_clones_of(source, spare);
+    }
+}
+
+impl<K, V, S> SieveCache<K, V, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    /// Fill an empty cache with copies of the entries of `source`, reusing `spare` nodes first.
+    fn relink_clones_of(&mut self, source: &Self, mut spare: Vec<Box<Node<K, V>>>) {
+        debug_assert!(self.len == 0 && self.head.is_none());
+        let mut src = source.tail;
+        while let Some(src_) = src {
+            let src_ref = unsafe { src_.as_ref() };
+            let mut node = match spare.pop() {
+                Some(mut node) => {
+                    node.key.clone_from(&src_ref.key);
+                    node.value.clone_from(&src_ref.value);
+                    node
+                }
+                None => Box::new(Node::new(src_ref.key.clone(), src_ref.value.clone())),
+            };
+            node.visited = src_ref.visited;
+            node.recent = src_ref.recent;
+            node.weight = src_ref.weight;
+            let node_ = NonNull::from(node.as_mut());
+            self.map.insert(src_ref.key.clone(), node);
+            self.add_node(node_);
+            if source.hand == Some(src_) {
+                self.hand = Some(node_);
+            }
+            self.len += 1;
+            self.weight += src_ref.weight;
+            src = src_ref.prev;
+        }
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, V, S: BuildHasher> IntoIterator for &'a SieveCache<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> Extend<(K, V)> for SieveCache<K, V, S> {
+    /// Insert every pair as with [`insert`](SieveCache::insert), possibly evicting older entries.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K, V, S> Extend<(&'a K, &'a V)> for SieveCache<K, V, S>
+where
+    K: Eq + Hash + Clone + 'a,
+    V: Clone + 'a,
+    S: BuildHasher,
+{
+    /// Insert a clone of every pair, so that `cache.extend(other.iter())` copies another cache.
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(
+            iter.into_iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+    }
+}
+
+/// An iterator over the entries of a [`SieveCache`], returned by [`SieveCache::iter`].
+pub struct Iter<'a, K: Eq + Hash + Clone, V> {
+    front: Option<NonNull<Node<K, V>>>,
+    back: Option<NonNull<Node<K, V>>>,
+    len: usize,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K: Eq + Hash + Clone, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let node_ref = unsafe { &*self.front?.as_ptr() };
+        self.front = node_ref.next;
+        self.len -= 1;
+        Some((&node_ref.key, &node_ref.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let node_ref = unsafe { &*self.back?.as_ptr() };
+        self.back = node_ref.prev;
+        self.len -= 1;
+        Some((&node_ref.key, &node_ref.value))
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K: Eq + Hash + Clone, V> FusedIterator for Iter<'_, K, V> {}
+
+/// An iterator over the keys of a [`SieveCache`], returned by [`SieveCache::keys`].
+pub struct Keys<'a, K: Eq + Hash + Clone, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K: Eq + Hash + Clone, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> DoubleEndedIterator for Keys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+impl<K: Eq + Hash + Clone, V> FusedIterator for Keys<'_, K, V> {}
+
+/// An iterator over the values of a [`SieveCache`], returned by [`SieveCache::values`].
+pub struct Values<'a, K: Eq + Hash + Clone, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K: Eq + Hash + Clone, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> DoubleEndedIterator for Values<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> ExactSizeIterator for Values<'_, K, V> {}
+
+impl<K: Eq + Hash + Clone, V> FusedIterator for Values<'_, K, V> {}
+
+/// Mutable access to a cached value that only counts as an access once dereferenced.
+///
+/// This is returned by [`SieveCache::access_mut`].
+pub struct AccessGuard<'a, K: Eq + Hash + Clone, V, S = RandomState> {
+    node: NonNull<Node<K, V>>,
+    cache: NonNull<SieveCache<K, V, S>>,
+    accessed: Cell<bool>,
+    marker: PhantomData<&'a mut SieveCache<K, V, S>>,
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> AccessGuard<'_, K, V, S> {
+    fn mark_accessed(&self) {
+        if !self.accessed.replace(true) {
+            // The guard borrows the cache mutably, so nothing else can access it meanwhile
+            unsafe { (*self.cache.as_ptr()).touch(self.node) }
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> Deref for AccessGuard<'_, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.mark_accessed();
+        unsafe { &(*self.node.as_ptr()).value }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> DerefMut for AccessGuard<'_, K, V, S> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.mark_accessed();
+        unsafe { &mut (*self.node.as_ptr()).value }
+    }
+}
+
+/// A view into a single entry of a [`SieveCache`], obtained with [`SieveCache::entry_ref`].
+pub enum EntryRef<'a, 'b, K: Eq + Hash + Clone, Q: ?Sized, V, S = RandomState> {
+    Occupied(OccupiedEntryRef<'a, K, V>),
+    Vacant(VacantEntryRef<'a, 'b, K, Q, V, S>),
+}
+
+/// An entry that is already present in the cache.
+pub struct OccupiedEntryRef<'a, K: Eq + Hash + Clone, V> {
+    node: NonNull<Node<K, V>>,
+    marker: PhantomData<&'a mut Node<K, V>>,
+}
+
+/// An entry that is not present in the cache yet.
+pub struct VacantEntryRef<'a, 'b, K: Eq + Hash + Clone, Q: ?Sized, V, S = RandomState> {
+    cache: &'a mut SieveCache<K, V, S>,
+    key: &'b Q,
+}
+
+impl<'a, K: Eq + Hash + Clone, Q, V, S: BuildHasher> EntryRef<'a, '_, K, Q, V, S>
+where
+    Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    K: Borrow<Q>,
+{
+    /// Return a mutable reference to the value, inserting `default` if the entry is vacant.
+    ///
+    /// This returns `None` if a new entry was needed but no existing entry could be evicted.
+    pub fn or_insert(self, default: V) -> Option<&'a mut V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Return a mutable reference to the value, inserting the result of `f` if the entry is vacant.
+    ///
+    /// `f` and the key conversion only run on the vacant path. This returns `None` if a new entry
+    /// was needed but no existing entry could be evicted.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> Option<&'a mut V> {
+        match self {
+            EntryRef::Occupied(entry) => Some(entry.into_mut()),
+            EntryRef::Vacant(entry) => {
+                if !entry.cache.may_make_room() {
+                    return None;
+                }
+                entry.insert(f())
+            }
+        }
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, V> OccupiedEntryRef<'a, K, V> {
+    /// Return the key of the entry.
+    pub fn key(&self) -> &K {
+        unsafe { &self.node.as_ref().key }
+    }
+
+    /// Get an immutable reference to the value of the entry.
+    pub fn get(&self) -> &V {
+        unsafe { &self.node.as_ref().value }
+    }
+
+    /// Get a mutable reference to the value of the entry.
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut self.node.as_mut().value }
+    }
+
+    /// Convert the entry into a mutable reference to its value, bound to the cache's lifetime.
+    pub fn into_mut(mut self) -> &'a mut V {
+        unsafe { &mut self.node.as_mut().value }
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, Q, V, S: BuildHasher> VacantEntryRef<'a, '_, K, Q, V, S>
+where
+    Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    K: Borrow<Q>,
+{
+    /// Return the key that was used to look up the entry.
+    pub fn key(&self) -> &Q {
+        self.key
+    }
+
+    /// Insert `value` under an owned copy of the key, possibly evicting another entry.
+    ///
+    /// This returns `None` if no existing entry could be evicted to make room.
+    pub fn insert(self, value: V) -> Option<&'a mut V> {
+        let mut node = self.cache.insert_new(self.key.to_owned(), value)?;
+        Some(unsafe { &mut node.as_mut().value })
+    }
+}
+
+#[test]
+fn test() {
+    let mut cache = SieveCache::new(3).unwrap();
+    assert!(cache.insert("foo".to_string(), "foocontent".to_string()).0);
+    assert!(cache.insert("bar".to_string(), "barcontent".to_string()).0);
+    cache.remove("bar");
+    assert!(
+        cache
+            .insert("bar2".to_string(), "bar2content".to_string())
+            .0
+    );
+    assert!(
+        cache
+            .insert("bar3".to_string(), "bar3content".to_string())
             .0
     );
-    assert_eq!(cache.get("foo"), Some(&"foocontent".to_string()));
-    assert_eq!(cache.get("bar"), None);
-    assert_eq!(cache.get("bar2"), Some(&"bar2content".to_string()));
-    assert_eq!(cache.get("bar3"), Some(&"bar3content".to_string()));
+    assert_eq!(cache.get("foo"), Some(&"foocontent".to_string()));
+    assert_eq!(cache.get("bar"), None);
+    assert_eq!(cache.get("bar2"), Some(&"bar2content".to_string()));
+    assert_eq!(cache.get("bar3"), Some(&"bar3content".to_string()));
+}
+
+#[test]
+fn test_visited_flag_update() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("key1".to_string(), "value1".to_string());
+    cache.insert("key2".to_string(), "value2".to_string());
+    // update `key1` entry.
+    cache.insert("key1".to_string(), "updated".to_string());
+    // new entry is added.
+    cache.insert("key3".to_string(), "value3".to_string());
+    assert_eq!(cache.get("key1"), Some(&"updated".to_string()));
+}
+
+#[cfg(test)]
+#[allow(clippy::ptr_arg)]
+fn evict_string_cond(_k: &String, v: &String) -> bool {
+    v.len() < 6
+}
+
+#[test]
+fn test_with_eviction() {
+    let mut cache = SieveCache::with_evict_condition(3, evict_string_cond).unwrap();
+    assert!(cache.insert("a".to_string(), "aaaaaa".to_string()).0);
+    assert!(cache.insert("b".to_string(), "bbbbbb".to_string()).0);
+    assert!(cache.insert("c".to_string(), "cccccc".to_string()).0);
+    assert!(!cache.insert("bar".to_string(), "barc".to_string()).1);
+    assert_eq!(cache.get("a"), Some(&"aaaaaa".to_string()));
+    assert_eq!(cache.get("b"), Some(&"bbbbbb".to_string()));
+    assert_eq!(cache.get("c"), Some(&"cccccc".to_string()));
+    assert_eq!(cache.get("bar"), None);
+}
+
+#[test]
+fn test_with_eviction_2() {
+    let mut cache = SieveCache::with_evict_condition(3, evict_string_cond).unwrap();
+    assert!(cache.insert("a".to_string(), "aaaaaa".to_string()).0);
+    assert!(cache.insert("b".to_string(), "bbbbbb".to_string()).0);
+    assert!(cache.insert("c".to_string(), "c".to_string()).0);
+    assert!(cache.insert("bar".to_string(), "barc".to_string()).1);
+    assert_eq!(cache.get("a"), Some(&"aaaaaa".to_string()));
+    assert_eq!(cache.get("b"), Some(&"bbbbbb".to_string()));
+    assert_eq!(cache.get("bar"), Some(&"barc".to_string()));
+    assert_eq!(cache.get("c"), None);
+}
+
+#[cfg(test)]
+thread_local! {
+    static PROBE_TO_OWNED_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// A borrowed key type that counts how many times it is converted into an owned key.
+#[cfg(test)]
+#[derive(Hash, PartialEq, Eq)]
+struct Probe(u32);
+
+#[cfg(test)]
+#[derive(Hash, PartialEq, Eq)]
+struct OwnedProbe(Probe);
+
+#[cfg(test)]
+impl Clone for OwnedProbe {
+    fn clone(&self) -> Self {
+        OwnedProbe(Probe(self.0 .0))
+    }
+}
+
+#[cfg(test)]
+impl Borrow<Probe> for OwnedProbe {
+    fn borrow(&self) -> &Probe {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl ToOwned for Probe {
+    type Owned = OwnedProbe;
+
+    fn to_owned(&self) -> OwnedProbe {
+        PROBE_TO_OWNED_CALLS.with(|calls| calls.set(calls.get() + 1));
+        OwnedProbe(Probe(self.0))
+    }
+}
+
+#[test]
+fn test_entry_ref() {
+    let mut cache: SieveCache<String, usize> = SieveCache::new(2).unwrap();
+    *cache.entry_ref("foo").or_insert(0).unwrap() += 1;
+    *cache.entry_ref("foo").or_insert(0).unwrap() += 1;
+    assert_eq!(cache.get("foo"), Some(&2));
+    match cache.entry_ref("bar") {
+        EntryRef::Vacant(entry) => assert_eq!(entry.key(), "bar"),
+        EntryRef::Occupied(_) => panic!("bar should be vacant"),
+    }
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn test_entry_ref_no_key_allocation_on_hit() {
+    let mut cache: SieveCache<OwnedProbe, &str> = SieveCache::new(2).unwrap();
+    let calls = || PROBE_TO_OWNED_CALLS.with(|calls| calls.get());
+    let before = calls();
+    assert_eq!(
+        cache.entry_ref(&Probe(1)).or_insert_with(|| "one"),
+        Some(&mut "one")
+    );
+    assert_eq!(calls(), before + 1);
+    assert_eq!(
+        cache
+            .entry_ref(&Probe(1))
+            .or_insert_with(|| panic!("must not be called on a hit")),
+        Some(&mut "one")
+    );
+    assert_eq!(calls(), before + 1);
+    assert!(cache.map.get(&Probe(1)).unwrap().visited);
+}
+
+#[test]
+fn test_take_stats() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    assert_eq!(cache.get("a"), Some(&1));
+    assert_eq!(cache.get("c"), None);
+    let first = cache.take_stats();
+    assert_eq!(
+        first,
+        CacheStats {
+            hits: 1,
+            misses: 1,
+            insertions: 2,
+            evictions: 0,
+            condition_vetoes: 0,
+            insert_rejections: 0,
+        }
+    );
+    assert_eq!(cache.stats(), CacheStats::default());
+
+    cache.insert("c", 3);
+    assert_eq!(cache.get("c"), Some(&3));
+    assert_eq!(cache.get("b"), None);
+    assert_eq!(cache.get("d"), None);
+    let second = cache.take_stats();
+    assert_eq!(
+        second,
+        CacheStats {
+            hits: 1,
+            misses: 2,
+            insertions: 1,
+            evictions: 1,
+            condition_vetoes: 0,
+            insert_rejections: 0,
+        }
+    );
+    assert!((second.hit_ratio() - 1.0 / 3.0).abs() < f64::EPSILON);
+    assert_eq!(cache.stats(), CacheStats::default());
+}
+
+#[test]
+fn test_set_capacity_grow_preserves_state() {
+    let fill = || {
+        let mut cache = SieveCache::new(3).unwrap();
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        cache.get("a");
+        cache.get("c");
+        cache.insert("d", 4);
+        cache.get("d");
+        cache
+    };
+    let mut grown = fill();
+    let mut reference = fill();
+    let hand = grown.hand;
+    fn visited(cache: &SieveCache<&'static str, i32>) -> Vec<(&'static str, bool)> {
+        let mut flags: Vec<_> = cache.map.iter().map(|(k, n)| (*k, n.visited)).collect();
+        flags.sort();
+        flags
+    }
+    let flags = visited(&grown);
+
+    grown.set_capacity(10).unwrap();
+    assert_eq!(grown.capacity(), 10);
+    assert_eq!(grown.hand, hand);
+    assert_eq!(visited(&grown), flags);
+
+    assert!(grown.evict());
+    assert!(reference.evict());
+    assert_eq!(visited(&grown), visited(&reference));
+    assert_eq!(
+        unsafe { grown.hand.map(|node| node.as_ref().key) },
+        unsafe { reference.hand.map(|node| node.as_ref().key) }
+    );
+}
+
+#[test]
+fn test_set_capacity_shrink() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for i in 0..4 {
+        cache.insert(i, i);
+    }
+    assert!(cache.set_capacity(0).is_err());
+    cache.set_capacity(2).unwrap();
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.capacity(), 2);
+    assert!(cache.contains_key(&2));
+    assert!(cache.contains_key(&3));
+}
+
+#[test]
+fn test_peek_victims() {
+    let mut cache = SieveCache::new(5).unwrap();
+    for i in 0..5 {
+        cache.insert(i, i * 10);
+    }
+    cache.get(&0);
+    cache.get(&2);
+    cache.insert(5, 50);
+    cache.get(&3);
+
+    let peeked: Vec<_> = cache
+        .peek_victims(2)
+        .into_iter()
+        .map(|(k, v)| (*k, *v))
+        .collect();
+    assert_eq!(cache.len(), 5);
+    assert!(cache.map.get(&3).unwrap().visited);
+
+    let mut evicted = Vec::new();
+    for _ in 0..2 {
+        let before: HashSet<_> = cache.map.keys().copied().collect();
+        assert!(cache.evict());
+        let after: HashSet<_> = cache.map.keys().copied().collect();
+        let key = *before.difference(&after).next().unwrap();
+        evicted.push((key, key * 10));
+    }
+    assert_eq!(peeked, evicted);
+}
+
+#[test]
+fn test_peek_victims_with_condition() {
+    let mut cache = SieveCache::with_evict_condition(3, evict_string_cond).unwrap();
+    cache.insert("a".to_string(), "aaaaaa".to_string());
+    cache.insert("b".to_string(), "b".to_string());
+    cache.insert("c".to_string(), "cccccc".to_string());
+    let peeked: Vec<_> = cache
+        .peek_victims(3)
+        .into_iter()
+        .map(|(k, _)| k.clone())
+        .collect();
+    assert_eq!(peeked, vec!["b".to_string()]);
+    assert!(cache.peek_victims(0).is_empty());
+}
+
+#[test]
+fn test_consistency_random_operations() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn evict_even_keys(key: &u8, _value: &u8) -> bool {
+        key.is_multiple_of(2)
+    }
+
+    let mut rng = StdRng::seed_from_u64(0x5137e);
+    for _ in 0..200 {
+        let capacity = rng.gen_range(1..6);
+        let mut cache = if rng.gen_bool(0.5) {
+            SieveCache::new(capacity).unwrap()
+        } else {
+            SieveCache::with_evict_condition(capacity, evict_even_keys).unwrap()
+        };
+        let mut shadow = HashMap::new();
+        for _ in 0..50 {
+            let key = rng.gen_range(0..10);
+            match rng.gen_range(0..4) {
+                0 => {
+                    if cache.insert(key, key).1 {
+                        shadow.insert(key, key);
+                    }
+                }
+                1 => {
+                    cache.get(&key);
+                }
+                2 => assert_eq!(cache.remove(&key), shadow.remove(&key)),
+                _ => {
+                    let _ = cache.set_capacity(rng.gen_range(1..6));
+                }
+            }
+            cache.check_consistency();
+            shadow.retain(|key, _| cache.contains_key(key));
+            assert_eq!(shadow.len(), cache.len());
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn test_arbitrary_operations() {
+    use arbitrary::{Arbitrary, Unstructured};
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(0xf022);
+    let mut bytes = vec![0; 4096];
+    for _ in 0..50 {
+        rng.fill_bytes(&mut bytes);
+        let mut input = Unstructured::new(&bytes);
+        let ops = Vec::<Operation<u8, u8>>::arbitrary(&mut input).unwrap();
+        let mut cache = SieveCache::new(4).unwrap();
+        for op in ops {
+            op.apply(&mut cache);
+            cache.check_consistency();
+        }
+    }
+    assert_eq!(
+        Operation::GetMut(1, 2).apply(&mut SieveCache::new(1).unwrap()),
+        None
+    );
+}
+
+#[test]
+fn test_regression_evict_after_removing_hand() {
+    // Nodes used to be freed before being unlinked, so evicting twice in a row, or evicting
+    // after removing the node under the hand, read freed memory.
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert(0, 0);
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.insert(3, 3);
+    let hand = unsafe { cache.hand.unwrap().as_ref().key };
+    assert_eq!(cache.remove(&hand), Some(hand));
+    cache.check_consistency();
+    cache.insert(4, 4);
+    cache.insert(5, 5);
+    cache.check_consistency();
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn test_get_or_insert_with_mut() {
+    let mut cache: SieveCache<&str, Vec<u32>> = SieveCache::new(2).unwrap();
+    cache.get_or_insert_with_mut("a", Vec::new).unwrap().push(1);
+    assert!(!cache.map.get("a").unwrap().visited);
+    cache
+        .get_or_insert_with_mut("a", || panic!("must not be called on a hit"))
+        .unwrap()
+        .push(2);
+    assert!(cache.map.get("a").unwrap().visited);
+    assert_eq!(cache.get_or_insert_with("b", || vec![3]), Some(&vec![3]));
+    assert_eq!(cache.get("a"), Some(&vec![1, 2]));
+}
+
+#[test]
+fn test_get_or_insert_with_mut_blocked() {
+    let mut cache = SieveCache::with_evict_condition(1, evict_string_cond).unwrap();
+    cache.insert("a".to_string(), "aaaaaa".to_string());
+    assert!(cache
+        .get_or_insert_with_mut("b".to_string(), || "b".to_string())
+        .is_none());
+    assert!(!cache.contains_key("b"));
+}
+
+/// Run the same access pattern against a cache using `policy`, and return the keys it retains.
+#[cfg(test)]
+fn behaves_like(policy: Policy) -> Vec<u32> {
+    let mut cache = SieveCache::with_policy(3, policy).unwrap();
+    cache.insert(1, ());
+    cache.insert(2, ());
+    cache.insert(3, ());
+    cache.get(&1);
+    cache.insert(4, ());
+    cache.get(&1);
+    cache.insert(5, ());
+    let mut keys: Vec<_> = cache.map.keys().copied().collect();
+    keys.sort();
+    keys
+}
+
+#[test]
+fn test_policy_sieve_vs_fifo() {
+    // SIEVE keeps the entry that keeps being accessed, FIFO evicts it as soon as it is the oldest.
+    assert_eq!(behaves_like(Policy::Sieve), vec![1, 4, 5]);
+    assert_eq!(behaves_like(Policy::Fifo), vec![3, 4, 5]);
+    assert_eq!(behaves_like(Policy::default()), behaves_like(Policy::Sieve));
+}
+
+#[test]
+fn test_eviction_policy_name() {
+    let sieve: SieveCache<u32, u32> = SieveCache::new(1).unwrap();
+    let fifo: SieveCache<u32, u32> = SieveCache::with_policy(1, Policy::Fifo).unwrap();
+    assert_eq!(sieve.eviction_policy_name(), "SIEVE");
+    assert_eq!(fifo.eviction_policy_name(), "FIFO");
+}
+
+#[test]
+fn test_fifo_peek_victims() {
+    let mut cache = SieveCache::with_policy(3, Policy::Fifo).unwrap();
+    cache.insert(1, ());
+    cache.insert(2, ());
+    cache.insert(3, ());
+    cache.get(&1);
+    let victims: Vec<_> = cache.peek_victims(2).into_iter().map(|(k, _)| *k).collect();
+    assert_eq!(victims, vec![1, 2]);
+}
+
+/// A policy that always evicts the most recently inserted entry.
+#[cfg(test)]
+#[derive(Clone)]
+struct EvictHead;
+
+#[cfg(test)]
+impl<K: Eq + Hash + Clone, V> EvictionPolicy<K, V> for EvictHead {
+    fn name(&self) -> &'static str {
+        "head"
+    }
+
+    fn select_victim<'a>(&mut self, list: &NodeList<'a, K, V>) -> Option<NodeRef<'a, K, V>> {
+        list.head()
+    }
+}
+
+#[test]
+fn test_custom_eviction_policy() {
+    let mut cache = SieveCache::with_eviction_policy(2, EvictHead).unwrap();
+    assert_eq!(cache.eviction_policy_name(), "head");
+    cache.insert(1, ());
+    cache.insert(2, ());
+    cache.get(&2);
+    cache.insert(3, ());
+    cache.insert(4, ());
+    cache.check_consistency();
+    let mut keys: Vec<_> = cache.map.keys().copied().collect();
+    keys.sort();
+    assert_eq!(keys, vec![1, 4]);
+    // No hook marks entries, so the default hooks leave them unvisited.
+    assert!(!cache.map.get(&1).unwrap().visited);
+    assert!(cache.peek_victims(1).is_empty());
+}
+
+#[test]
+fn test_builtin_policies_as_custom() {
+    let custom = {
+        let mut cache = SieveCache::with_eviction_policy(3, Sieve).unwrap();
+        cache.insert(1, ());
+        cache.insert(2, ());
+        cache.insert(3, ());
+        cache.get(&1);
+        cache.insert(4, ());
+        cache.get(&1);
+        cache.insert(5, ());
+        let mut keys: Vec<_> = cache.map.keys().copied().collect();
+        keys.sort();
+        keys
+    };
+    assert_eq!(custom, behaves_like(Policy::Sieve));
+}
+
+#[test]
+fn test_contains_keys() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a".to_string(), 1);
+    cache.insert("b".to_string(), 2);
+    assert!(cache.contains_keys(["a", "b"]));
+    assert!(!cache.contains_keys(["a", "c"]));
+    assert!(cache.contains_keys::<str, _>([]));
+    assert!(cache.contains_any(["c", "b"]));
+    assert!(!cache.contains_any(["c", "d"]));
+    assert!(!cache.contains_any::<str, _>([]));
+    assert!(!cache.map.get("a").unwrap().visited);
+}
+
+#[test]
+fn test_hasher() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasherDefault;
+
+    type FixedState = BuildHasherDefault<DefaultHasher>;
+    let mut cache = SieveCache::with_hasher(4, FixedState::default()).unwrap();
+    cache.insert("foo", 1);
+    assert_eq!(cache.get("foo"), Some(&1));
+    let hash = cache.hasher().hash_one("foo");
+    assert_eq!(hash, FixedState::default().hash_one("foo"));
+    assert_eq!(hash, cache.hasher().hash_one("foo"));
+}
+
+#[test]
+fn test_maybe_compact() {
+    let mut cache = SieveCache::new(1000).unwrap();
+    for i in 0..1000 {
+        cache.insert(i, i.to_string());
+    }
+    for i in 10..1000 {
+        cache.remove(&i);
+    }
+    let before = cache.map.capacity();
+    cache.set_compaction_threshold(0.0);
+    cache.maybe_compact();
+    assert_eq!(cache.map.capacity(), before);
+    cache.set_compaction_threshold(0.25);
+    cache.maybe_compact();
+    assert!(cache.map.capacity() < before);
+    cache.check_consistency();
+    for i in 0..10 {
+        assert_eq!(cache.get(&i), Some(&i.to_string()));
+    }
+    let compacted = cache.map.capacity();
+    cache.maybe_compact();
+    assert_eq!(cache.map.capacity(), compacted);
+}
+
+#[test]
+fn test_insert_result() {
+    let mut cache = SieveCache::with_evict_condition(2, evict_string_cond).unwrap();
+    assert_eq!(
+        cache.insert_result("a".to_string(), "a".to_string()),
+        InsertOutcome::Inserted
+    );
+    assert_eq!(
+        cache.insert_result("b".to_string(), "bbbbbb".to_string()),
+        InsertOutcome::Inserted
+    );
+    assert_eq!(
+        cache.insert_result("a".to_string(), "aaaaaa".to_string()),
+        InsertOutcome::Updated
+    );
+    assert_eq!(
+        cache.insert_result("c".to_string(), "c".to_string()),
+        InsertOutcome::Rejected
+    );
+    assert_eq!(cache.get("a"), Some(&"aaaaaa".to_string()));
+    assert_eq!(cache.get("c"), None);
+}
+
+#[test]
+fn test_get_pair_mut() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("alice".to_string(), 100);
+    cache.insert("bob".to_string(), 20);
+    if let (Some(from), Some(to)) = cache.get_pair_mut("alice", "bob") {
+        *from -= 30;
+        *to += 30;
+    }
+    assert_eq!(cache.get("alice"), Some(&70));
+    assert_eq!(cache.get("bob"), Some(&50));
+    assert!(matches!(cache.get_pair_mut("alice", "alice"), (None, None)));
+    let (found, missing) = cache.get_pair_mut("bob", "carol");
+    assert_eq!(found, Some(&mut 50));
+    assert_eq!(missing, None);
+}
+
+#[test]
+fn test_estimated_memory_usage() {
+    let mut cache: SieveCache<u64, u64> = SieveCache::new(16).unwrap();
+    for i in 0..10 {
+        cache.insert(i, i);
+    }
+    // A node holds a key, a value, two links, a visited flag and a weight.
+    let node_size = std::mem::size_of::<Node<u64, u64>>();
+    #[cfg(target_pointer_width = "64")]
+    assert_eq!(node_size, 48);
+    let expected = std::mem::size_of::<SieveCache<u64, u64>>()
+        + 10 * node_size
+        + cache.map.capacity() * (std::mem::size_of::<(u64, usize)>() + 1);
+    assert_eq!(cache.estimated_memory_usage(), expected);
+    assert_eq!(
+        cache.estimated_memory_usage_with(&|_: &u64, _: &u64| 8),
+        expected + 80
+    );
+}
+
+#[test]
+fn test_first_unvisited() {
+    let mut cache = SieveCache::new(4).unwrap();
+    assert_eq!(cache.first_unvisited(), None);
+    for i in 0..4 {
+        cache.insert(i, i * 10);
+    }
+    cache.get(&0);
+    cache.get(&1);
+    cache.get(&3);
+    assert_eq!(cache.first_unvisited(), Some((&2, &20)));
+    assert!(cache.map.get(&0).unwrap().visited);
+    cache.get(&2);
+    assert_eq!(cache.first_unvisited(), None);
+}
+
+#[test]
+fn test_get_or_insert_with_ref() {
+    let mut cache: SieveCache<OwnedProbe, u32> = SieveCache::new(2).unwrap();
+    let calls = || PROBE_TO_OWNED_CALLS.with(|calls| calls.get());
+    let before = calls();
+    assert_eq!(cache.get_or_insert_with_ref(&Probe(7), || 7), Some(&7));
+    assert_eq!(calls(), before + 1);
+    assert_eq!(cache.get_or_insert_with_ref(&Probe(7), || 8), Some(&7));
+    assert_eq!(calls(), before + 1);
+
+    let mut strings: SieveCache<String, usize> = SieveCache::new(2).unwrap();
+    assert_eq!(strings.get_or_insert_with_ref("foo", || 3), Some(&3));
+    assert_eq!(strings.get_or_insert_with_ref("foo", || 4), Some(&3));
+}
+
+#[test]
+fn test_reserve() {
+    let mut cache: SieveCache<u32, u32> = SieveCache::new(4).unwrap();
+    cache.insert(1, 1);
+    cache.reserve(100);
+    assert!(cache.map.capacity() >= 101);
+    cache.reserve_exact(200);
+    assert!(cache.map.capacity() >= 201);
+    assert!(cache.try_reserve(10).is_ok());
+    assert!(cache.try_reserve(usize::MAX).is_err());
+    assert_eq!(cache.capacity(), 4);
+    assert_eq!(cache.get(&1), Some(&1));
+}
+
+/// Return the entries of `cache` from head to tail, along with their visited flags.
+#[cfg(test)]
+fn entries<K: Eq + Hash + Clone, V: Clone, S>(cache: &SieveCache<K, V, S>) -> Vec<(K, V, bool)> {
+    let mut entries = Vec::new();
+    let mut node = cache.head;
+    while let Some(node_) = node {
+        let node_ref = unsafe { node_.as_ref() };
+        entries.push((
+            node_ref.key.clone(),
+            node_ref.value.clone(),
+            node_ref.visited,
+        ));
+        node = node_ref.next;
+    }
+    entries
+}
+
+#[test]
+fn test_clone_from() {
+    let mut source = SieveCache::new(4).unwrap();
+    for i in 0..5 {
+        source.insert(i, i.to_string());
+    }
+    source.get(&2);
+    source.get(&4);
+    let mut target = SieveCache::new(8).unwrap();
+    for i in 10..17 {
+        target.insert(i, "x".repeat(i));
+    }
+    target.clone_from(&source);
+    target.check_consistency();
+    assert_eq!(entries(&target), entries(&source));
+    assert_eq!(target.capacity(), 4);
+    let hand_key =
+        |cache: &SieveCache<usize, String>| unsafe { cache.hand.map(|n| n.as_ref().key) };
+    assert_eq!(hand_key(&target), hand_key(&source));
+
+    // Both caches now evict the same entries.
+    let mut clone = source.clone();
+    clone.check_consistency();
+    for cache in [&mut source, &mut target, &mut clone] {
+        cache.insert(5, "5".to_string());
+        cache.insert(6, "6".to_string());
+    }
+    assert_eq!(entries(&target), entries(&source));
+    assert_eq!(entries(&clone), entries(&source));
+
+    // The hasher is copied too
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Seeded(u64);
+
+    impl BuildHasher for Seeded {
+        type Hasher = DefaultHasher;
+
+        fn build_hasher(&self) -> DefaultHasher {
+            let mut hasher = DefaultHasher::new();
+            hasher.write_u64(self.0);
+            hasher
+        }
+    }
+
+    let mut source = SieveCache::with_hasher(4, Seeded(1)).unwrap();
+    source.insert(1, 1);
+    let mut target = SieveCache::with_hasher(4, Seeded(2)).unwrap();
+    target.insert(2, 2);
+    target.clone_from(&source);
+    assert_eq!(target.hasher(), &Seeded(1));
+    assert_eq!(target.get(&1), Some(&1));
+    target.check_consistency();
+}
+
+#[test]
+fn test_is_full() {
+    let mut cache = SieveCache::new(3).unwrap();
+    for i in 0..3 {
+        assert!(!cache.is_full());
+        cache.insert(i, i);
+    }
+    assert!(cache.is_full());
+    cache.insert(3, 3);
+    assert!(cache.is_full());
+    cache.remove(&3);
+    assert!(!cache.is_full());
+}
+
+#[test]
+fn test_get_or_insert_checked() {
+    let mut cache = SieveCache::with_evict_condition(2, evict_string_cond).unwrap();
+    assert_eq!(
+        cache.get_or_insert_checked("a".to_string(), || "aaaaaa".to_string()),
+        Ok((&"aaaaaa".to_string(), true))
+    );
+    assert_eq!(
+        cache.get_or_insert_checked("a".to_string(), || "other".to_string()),
+        Ok((&"aaaaaa".to_string(), false))
+    );
+    cache.insert("b".to_string(), "bbbbbb".to_string());
+    assert_eq!(
+        cache.get_or_insert_checked("c".to_string(), || "c".to_string()),
+        Err(CacheError::EvictionBlocked)
+    );
+    assert_eq!(
+        CacheError::EvictionBlocked.to_string(),
+        "no entry could be evicted"
+    );
+}
+
+#[test]
+fn test_disabled() {
+    let mut cache = SieveCache::disabled();
+    assert_eq!(cache.capacity(), 0);
+    assert_eq!(
+        cache.insert_result("a".to_string(), 1),
+        InsertOutcome::Rejected
+    );
+    assert_eq!(cache.get("a"), None);
+    assert_eq!(cache.get_or_insert_with("b".to_string(), || 2), None);
+    assert_eq!(cache.entry_ref("c").or_insert(3), None);
+    assert_eq!(cache.len(), 0);
+    assert!(cache.is_empty());
+    cache.check_consistency();
+}
+
+#[test]
+fn test_retain_with_visited() {
+    let mut cache = SieveCache::new(5).unwrap();
+    for i in 0..5 {
+        cache.insert(i, i);
+    }
+    // Put the hand on an entry that is going to be dropped.
+    cache.hand = cache.map.get(&1).map(|node| NonNull::from(node.as_ref()));
+    cache.get(&0);
+    cache.get(&3);
+    cache.retain_with_visited(|_, value, visited| {
+        *value *= 10;
+        visited
+    });
+    cache.check_consistency();
+    assert_eq!(entries(&cache), vec![(3, 30, true), (0, 0, true)]);
+    assert_eq!(unsafe { cache.hand.map(|node| node.as_ref().key) }, Some(3));
+    cache.retain(|key, _| *key != 0);
+    cache.check_consistency();
+    assert_eq!(entries(&cache), vec![(3, 30, true)]);
+}
+
+#[test]
+fn test_access_mut() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    assert!(cache.access_mut("a").is_some());
+    assert!(!cache.map.get("a").unwrap().visited);
+    assert!(cache.access_mut("c").is_none());
+
+    {
+        let mut guard = cache.access_mut("b").unwrap();
+        *guard += 1;
+        assert_eq!(*guard, 3);
+    }
+    assert!(cache.map.get("b").unwrap().visited);
+    assert_eq!(cache.stats().hits, 2);
+
+    // Under LRU, the first dereference moves the entry to the head like `get_mut` does
+    let mut cache = SieveCache::with_policy(3, Policy::Lru).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    *cache.access_mut("a").unwrap() += 1;
+    cache.insert("d", 4);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["d", "a", "c"]);
+    assert_eq!(cache.peek("a"), Some(&2));
+    cache.check_consistency();
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_metrics_recorder() {
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct TestRecorder {
+        values: Mutex<HashMap<String, f64>>,
+    }
+
+    impl TestRecorder {
+        fn get(&self, name: &str) -> f64 {
+            self.values
+                .lock()
+                .unwrap()
+                .get(name)
+                .copied()
+                .unwrap_or(0.0)
+        }
+    }
+
+    impl MetricsRecorder for TestRecorder {
+        fn increment_counter(&self, name: &str, value: u64) {
+            *self
+                .values
+                .lock()
+                .unwrap()
+                .entry(name.to_owned())
+                .or_default() += value as f64;
+        }
+
+        fn set_gauge(&self, name: &str, value: f64) {
+            self.values.lock().unwrap().insert(name.to_owned(), value);
+        }
+    }
+
+    let recorder = Arc::new(TestRecorder::default());
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", 1);
+    cache.set_metrics_recorder("sieve_cache_", recorder.clone());
+    assert_eq!(recorder.get("sieve_cache_len"), 1.0);
+    cache.insert("b", 2);
+    assert_eq!(cache.get("a"), Some(&1));
+    assert_eq!(cache.get("c"), None);
+    cache.insert("c", 3);
+    assert_eq!(recorder.get("sieve_cache_hits"), 1.0);
+    assert_eq!(recorder.get("sieve_cache_misses"), 1.0);
+    assert_eq!(recorder.get("sieve_cache_evictions"), 1.0);
+    assert_eq!(recorder.get("sieve_cache_len"), 2.0);
+    cache.remove("a");
+    assert_eq!(recorder.get("sieve_cache_len"), 1.0);
+    cache.clear();
+    assert_eq!(recorder.get("sieve_cache_len"), 0.0);
+
+    cache.clear_metrics_recorder();
+    cache.get("a");
+    assert_eq!(recorder.get("sieve_cache_misses"), 1.0);
+}
+
+#[test]
+fn test_serialize_keys_round_trip() {
+    let mut cache = SieveCache::new(3).unwrap();
+    for i in 0..4u32 {
+        cache.insert(i, i * 10);
+    }
+    let keys = cache.serialize_keys();
+    assert_eq!(keys, vec![1, 2, 3]);
+    let json = serde_json::to_string(&keys).unwrap();
+
+    let keys: Vec<u32> = serde_json::from_str(&json).unwrap();
+    let restored =
+        SieveCache::from_keys_with_loader(3, keys, |key| (*key != 2).then_some(key * 10)).unwrap();
+    restored.check_consistency();
+    assert_eq!(entries(&restored), vec![(3, 30, false), (1, 10, false)]);
+}
+
+#[test]
+fn test_max_scan() {
+    let mut cache = SieveCache::with_max_scan(4, 2).unwrap();
+    for i in 0..4 {
+        cache.insert(i, i);
+        cache.get(&i);
+    }
+    let peeked: Vec<_> = cache.peek_victims(1).into_iter().map(|(k, _)| *k).collect();
+    // The two oldest entries get their flags cleared, then the third one is evicted.
+    assert_eq!(cache.insert_result(4, 4), InsertOutcome::Inserted);
+    assert_eq!(peeked, vec![2]);
+    assert!(!cache.contains_key(&2));
+    assert_eq!(cache.stats().evictions, 1);
+    assert!(!cache.map.get(&0).unwrap().visited);
+    assert!(!cache.map.get(&1).unwrap().visited);
+    assert!(cache.map.get(&3).unwrap().visited);
+
+    // Without a bound, a fully visited cache clears every flag and wraps around to the oldest entry.
+    let mut unbounded = SieveCache::new(4).unwrap();
+    for i in 0..4 {
+        unbounded.insert(i, i);
+        unbounded.get(&i);
+    }
+    assert_eq!(unbounded.insert_result(4, 4), InsertOutcome::Inserted);
+    assert!(!unbounded.contains_key(&0));
+    assert!((1..4).all(|i| unbounded.visited(&i) == Some(false)));
+}
+
+#[test]
+fn test_visited() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.get("a");
+    assert_eq!(cache.visited("a"), Some(true));
+    assert_eq!(cache.visited("b"), Some(false));
+    assert_eq!(cache.visited("c"), None);
+    assert_eq!(cache.visited("b"), Some(false));
+}
+
+#[test]
+fn test_append_in_order() {
+    let snapshot = vec![(3, 30, true), (1, 10, false), (2, 20, true)];
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.append_in_order(snapshot.clone());
+    cache.check_consistency();
+    assert_eq!(entries(&cache), snapshot);
+
+    // Restoring behaves like the original cache: 1 is the first unvisited entry from the tail.
+    cache.insert(4, 40);
+    assert_eq!(
+        entries(&cache),
+        vec![(4, 40, false), (3, 30, true), (2, 20, false)]
+    );
+
+    // Existing keys are updated in place; the hand now sits on 3, which is no longer visited.
+    cache.append_in_order([(3, 33, false), (5, 50, true)]);
+    cache.check_consistency();
+    assert_eq!(
+        entries(&cache),
+        vec![(4, 40, false), (2, 20, false), (5, 50, true)]
+    );
+}
+
+#[test]
+fn test_lru_policy() {
+    let mut cache = SieveCache::with_policy(3, Policy::Lru).unwrap();
+    assert_eq!(cache.eviction_policy_name(), "LRU");
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    assert_eq!(cache.get(&"a"), Some(&1));
+    assert_eq!(entries(&cache)[0].0, "a");
+    *cache.get_mut(&"b").unwrap() = 20;
+    let keys: Vec<_> = entries(&cache).into_iter().map(|(k, _, _)| k).collect();
+    assert_eq!(keys, vec!["b", "a", "c"]);
+    cache.check_consistency();
+
+    // The least recently used entry goes first, even though every entry was visited.
+    assert_eq!(cache.peek_victims(1), vec![(&"c", &3)]);
+    cache.insert("d", 4);
+    assert!(!cache.contains_key(&"c"));
+    cache.insert("a", 10);
+    cache.insert("e", 5);
+    assert!(!cache.contains_key(&"b"));
+    let keys: Vec<_> = entries(&cache).into_iter().map(|(k, _, _)| k).collect();
+    assert_eq!(keys, vec!["e", "a", "d"]);
+    cache.check_consistency();
+}
+
+#[test]
+fn test_map_capacity() {
+    let mut cache = SieveCache::new(100).unwrap();
+    assert!(cache.map_capacity() >= 100);
+    for i in 0..150 {
+        cache.insert(i, i);
+        assert!(cache.map_capacity() >= cache.len());
+    }
+    assert!(cache.map_capacity() >= 100);
+}
+
+#[test]
+fn test_get_many_mut_map() {
+    let mut cache = SieveCache::new(4).unwrap();
+    cache.insert("a".to_string(), 1);
+    cache.insert("b".to_string(), 2);
+    cache.insert("c".to_string(), 3);
+    let keys = [
+        "a".to_string(),
+        "x".to_string(),
+        "c".to_string(),
+        "a".to_string(),
+    ];
+    let mut found = cache.get_many_mut_map(&keys);
+    assert_eq!(found.len(), 2);
+    assert!(!found.contains_key("x"));
+    for value in found.values_mut() {
+        **value *= 10;
+    }
+    assert_eq!(cache.get("a"), Some(&10));
+    assert_eq!(cache.get("b"), Some(&2));
+    assert_eq!(cache.get("c"), Some(&30));
+    assert_eq!(cache.stats().misses, 1);
+}
+
+#[test]
+fn test_iter_double_ended() {
+    let mut cache = SieveCache::new(5).unwrap();
+    for i in 0..5 {
+        cache.insert(i, i * 10);
+    }
+    cache.get(&2);
+    let keys: Vec<_> = cache.iter().map(|(k, _)| *k).collect();
+    assert_eq!(keys, vec![4, 3, 2, 1, 0]);
+    let keys: Vec<_> = cache.iter().rev().map(|(k, _)| *k).collect();
+    assert_eq!(keys, vec![0, 1, 2, 3, 4]);
+    assert_eq!(cache.visited(&3), Some(false));
+
+    let mut iter = cache.iter();
+    assert_eq!(iter.next(), Some((&4, &40)));
+    assert_eq!(iter.next_back(), Some((&0, &0)));
+    assert_eq!(iter.next_back(), Some((&1, &10)));
+    assert_eq!(iter.next(), Some((&3, &30)));
+    assert_eq!(iter.len(), 1);
+    assert_eq!(iter.next_back(), Some((&2, &20)));
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+    assert_eq!(iter.next(), None);
+    assert_eq!((&cache).into_iter().count(), 5);
+}
+
+/// Run `future` to completion by polling it in a loop, since the crate has no async runtime to
+/// test with. The futures of the tests never wait on anything, so they are ready at once.
+#[cfg(all(test, feature = "async"))]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_try_get_or_insert_with_async() {
+    #[derive(Debug, PartialEq)]
+    enum LoadError {
+        Unavailable,
+        Cache(CacheError),
+    }
+
+    impl From<CacheError> for LoadError {
+        fn from(err: CacheError) -> Self {
+            LoadError::Cache(err)
+        }
+    }
+
+    let mut cache: SieveCache<u32, String> = SieveCache::new(2).unwrap();
+    let loaded = block_on(
+        cache.try_get_or_insert_with_async(1, || async { Ok::<_, LoadError>("one".to_string()) }),
+    );
+    assert_eq!(loaded, Ok(&"one".to_string()));
+
+    let hit =
+        block_on(cache.try_get_or_insert_with_async(1, || async {
+            Err::<String, _>(LoadError::Unavailable)
+        }));
+    assert_eq!(hit, Ok(&"one".to_string()));
+
+    let failed =
+        block_on(cache.try_get_or_insert_with_async(2, || async {
+            Err::<String, _>(LoadError::Unavailable)
+        }));
+    assert_eq!(failed, Err(LoadError::Unavailable));
+    assert!(!cache.contains_key(&2));
+    assert_eq!(cache.len(), 1);
+    cache.check_consistency();
+
+    let mut cache = SieveCache::with_evict_condition(1, |_: &u32, _: &String| false).unwrap();
+    cache.insert(1, "one".to_string());
+    let blocked =
+        block_on(cache.try_get_or_insert_with_async(2, || async {
+            Err::<String, _>(LoadError::Unavailable)
+        }));
+    assert_eq!(blocked, Err(LoadError::Cache(CacheError::EvictionBlocked)));
+}
+
+#[test]
+fn test_weight_histogram() {
+    let mut cache: SieveCache<u32, String> =
+        SieveCache::with_weigher(100, |_, v: &String| v.len()).unwrap();
+    for (i, len) in [1, 3, 4, 8, 10, 20, 40].into_iter().enumerate() {
+        cache.insert(i as u32, "x".repeat(len));
+    }
+    assert_eq!(cache.weight(), 86);
+    assert_eq!(cache.weight_histogram(&[4, 10, 32]), vec![3, 2, 1, 1]);
+    assert_eq!(cache.weight_histogram(&[]), vec![7]);
+
+    // Going over the budget evicts entries until the total weight fits again.
+    cache.insert(7, "x".repeat(30));
+    assert!(cache.weight() <= 100);
+    cache.check_consistency();
+    assert!(!cache.insert(8, "x".repeat(101)).1);
+    *cache.get_mut(&7).unwrap() = String::new();
+    assert_eq!(cache.weight_histogram(&[0])[0], 1);
+}
+
+#[test]
+fn test_remove_fast() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    cache.get(&"b");
+    cache.insert("d", 4);
+    let stats = cache.stats();
+    assert_eq!(cache.remove_fast(&"b"), Some(2));
+    assert_eq!(cache.remove_fast(&"b"), None);
+    assert_eq!(cache.stats(), stats);
+    cache.check_consistency();
+    assert_eq!(entries(&cache), vec![("d", 4, false), ("c", 3, false)]);
+    for key in ["e", "f", "g"] {
+        cache.insert(key, 0);
+        cache.check_consistency();
+    }
+    assert_eq!(cache.len(), 3);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "reentrant use of a SyncSieveCache")]
+fn test_sync_reentrant_insert_panics() {
+    let cache = SyncSieveCache::new(2).unwrap();
+    cache.with_lock(|inner| {
+        inner.get_or_insert_with(1, || {
+            cache.insert(2, 2);
+            1
+        });
+    });
+}
+
+#[test]
+fn test_sync_lock_released_after_panic() {
+    let cache = SyncSieveCache::new(2).unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        cache.with_lock(|_| panic!("loader failed"));
+    }));
+    assert!(result.is_err());
+    assert!(cache.insert(1, 1));
+    assert_eq!(cache.get(&1), Some(1));
+}
+
+#[test]
+fn test_sync_approx_len() {
+    let cache = SyncSieveCache::new(1000).unwrap();
+    std::thread::scope(|scope| {
+        for t in 0..4u32 {
+            let cache = &cache;
+            scope.spawn(move || {
+                for i in 0..500 {
+                    cache.insert(t * 500 + i, i);
+                    let _ = cache.approx_len();
+                    if i % 3 == 0 {
+                        cache.remove(&(t * 500 + i / 2));
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(cache.approx_len(), cache.len());
+    assert!(cache.len() <= 1000);
+    cache.with_lock(|cache| cache.check_consistency());
+    cache.with_lock(|cache| cache.retain(|_, _| false));
+    assert_eq!(cache.approx_len(), 0);
+}
+
+#[cfg(test)]
+thread_local! {
+    static PANIC_ON_EVICT: Cell<bool> = const { Cell::new(false) };
+}
+
+#[test]
+fn test_panic_in_user_code() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    fn evict_unless_armed(_: &u32, _: &u32) -> bool {
+        assert!(!PANIC_ON_EVICT.get(), "evict condition panicked");
+        true
+    }
+
+    let mut cache = SieveCache::with_evict_condition(3, evict_unless_armed).unwrap();
+    for i in 0..3 {
+        cache.insert(i, i);
+    }
+    cache.get(&0);
+    PANIC_ON_EVICT.set(true);
+    let result = catch_unwind(AssertUnwindSafe(|| cache.insert(3, 3)));
+    PANIC_ON_EVICT.set(false);
+    assert!(result.is_err());
+    cache.check_consistency();
+    assert_eq!(cache.len(), 3);
+    assert!(!cache.contains_key(&3));
+
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        cache.get_or_insert_with(4, || panic!("loader panicked"));
+    }));
+    assert!(result.is_err());
+    cache.check_consistency();
+
+    assert_eq!(cache.insert(3, 3), (true, true));
+    assert_eq!(cache.len(), 3);
+    cache.check_consistency();
+}
+
+#[test]
+fn test_get_or_refresh() {
+    let mut cache = SieveCache::new(2).unwrap();
+    let is_stale = |v: &(u32, u32)| v.1 < 2;
+    assert_eq!(
+        cache.get_or_refresh("a", is_stale, || (1, 1)),
+        Some(&(1, 1))
+    );
+    assert_eq!(
+        cache.get_or_refresh("a", is_stale, || (2, 2)),
+        Some(&(2, 2))
+    );
+    assert_eq!(
+        cache.get_or_refresh("a", is_stale, || unreachable!()),
+        Some(&(2, 2))
+    );
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.insertions), (1, 2, 1));
+    assert_eq!(cache.len(), 1);
+    cache.check_consistency();
+}
+
+#[test]
+fn test_sample() {
+    let mut cache = SieveCache::new(100).unwrap();
+    assert!(cache.sample(5).is_empty());
+    for i in 0..100 {
+        cache.insert(i, i);
+    }
+    let sample = cache.sample(5);
+    assert_eq!(sample.len(), 5);
+    assert_eq!(sample[0], (&99, &99));
+    assert_eq!(sample[1], (&79, &79));
+    assert_eq!(cache.sample(500).len(), 100);
+    assert!(cache.sample(0).is_empty());
+    assert!(entries(&cache).iter().all(|(_, _, visited)| !visited));
+}
+
+#[test]
+fn test_evict_listener() {
+    use std::sync::{Arc, Mutex};
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+    let log = evicted.clone();
+    cache.set_evict_listener(Box::new(move |k, v| log.lock().unwrap().push((k, v))));
+    cache.insert(3, "three");
+    cache.remove(&3);
+    cache.insert(4, "four");
+    cache.insert(5, "five");
+    assert_eq!(*evicted.lock().unwrap(), vec![(1, "one"), (2, "two")]);
+    assert!(cache.clone().take_evict_listener().is_none());
+
+    assert!(cache.take_evict_listener().is_some());
+    cache.insert(6, "six");
+    assert_eq!(evicted.lock().unwrap().len(), 2);
+    assert!(cache.take_evict_listener().is_none());
+    cache.check_consistency();
+}
+
+#[test]
+fn test_insert_over_capacity() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.insert_over_capacity(3, 3);
+    cache.insert_over_capacity(4, 4);
+    cache.insert_over_capacity(1, 10);
+    assert_eq!(cache.len(), 4);
+    cache.check_consistency();
+
+    assert_eq!(cache.trim_to_capacity(), vec![(2, 2), (3, 3)]);
+    assert_eq!(cache.len(), 2);
+    assert!(cache.trim_to_capacity().is_empty());
+    assert_eq!(cache.stats().evictions, 2);
+    cache.check_consistency();
+    assert_eq!(cache.get(&1), Some(&10));
+    assert_eq!(cache.get(&4), Some(&4));
+}
+
+#[test]
+fn test_order_string() {
+    let mut cache = SieveCache::new(3).unwrap();
+    assert_eq!(cache.order_string(), "");
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    cache.get(&"a");
+    cache.get(&"c");
+    assert_eq!(cache.order_string(), "c* -> b -> a*");
+    cache.insert("d", 4);
+    assert_eq!(cache.order_string(), "d -> c* -> a (hand@c)");
+}
+
+#[test]
+fn test_compare_and_update() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", 1);
+    assert!(!cache.compare_and_update(&"a", &2, 3));
+    assert_eq!(cache.visited(&"a"), Some(false));
+    assert!(cache.compare_and_update(&"a", &1, 3));
+    assert_eq!(cache.visited(&"a"), Some(true));
+    assert!(!cache.compare_and_update(&"b", &1, 3));
+    assert_eq!(cache.get(&"a"), Some(&3));
+    assert!(!cache.contains_key(&"b"));
+}
+
+#[test]
+fn test_keys_matching() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for key in ["user:1", "user:2", "session:1", "user:3"] {
+        cache.insert(key.to_string(), ());
+    }
+    let mut keys = cache.keys_matching(|key| key.starts_with("user:"));
+    keys.sort();
+    assert_eq!(keys, vec!["user:1", "user:2", "user:3"]);
+    assert!(cache.keys_matching(|key| key.is_empty()).is_empty());
+    assert!(entries(&cache).iter().all(|(_, _, visited)| !visited));
+}
+
+#[test]
+fn test_warm() {
+    let mut cache = SieveCache::new(4).unwrap();
+    cache.warm([("hot1", 1), ("hot2", 2)]);
+    assert_eq!(cache.visited(&"hot1"), Some(true));
+    cache.insert("cold1", 3);
+    cache.insert("cold2", 4);
+    cache.insert("new1", 5);
+    cache.insert("new2", 6);
+    assert!(cache.contains_key(&"hot1"));
+    assert!(cache.contains_key(&"hot2"));
+    assert!(!cache.contains_key(&"cold1"));
+    assert!(!cache.contains_key(&"cold2"));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_evict_with_report() {
+    let mut cache = SieveCache::new(5).unwrap();
+    for i in 0..5 {
+        cache.insert(i, i * 10);
+    }
+    cache.get(&0);
+    cache.get(&1);
+    cache.get(&3);
+    let report = cache.evict_with_report();
+    assert_eq!(report.evicted, Some((2, 20)));
+    assert_eq!(report.nodes_scanned, 3);
+    assert_eq!(report.flags_cleared, 2);
+    assert_eq!(cache.visited(&3), Some(true));
+    cache.check_consistency();
+
+    let report = cache.evict_with_report();
+    assert_eq!(report.evicted, Some((4, 40)));
+    assert_eq!((report.nodes_scanned, report.flags_cleared), (2, 1));
+
+    let mut cache: SieveCache<u32, u32> = SieveCache::new(1).unwrap();
+    let report = cache.evict_with_report();
+    assert_eq!(report.evicted, None);
+    assert_eq!((report.nodes_scanned, report.flags_cleared), (0, 0));
+}
+
+#[test]
+fn test_array_cache() {
+    let mut cache: ArraySieveCache<String, u32, 4> = ArraySieveCache::new();
+    assert_eq!(cache.capacity(), 4);
+    for (i, key) in ["a", "b", "c", "d"].into_iter().enumerate() {
+        assert!(cache.insert(key.to_string(), i as u32));
+    }
+    assert!(!cache.insert("a".to_string(), 10));
+    assert_eq!(cache.get("c"), Some(&2));
+    assert!(cache.insert("e".to_string(), 4));
+    assert_eq!(cache.len(), 4);
+    assert!(!cache.contains_key("b"));
+    assert!(cache.contains_key("a"));
+
+    // Every entry is visited: the hand clears all the flags, then evicts where it started.
+    cache.get("d");
+    cache.get("e");
+    *cache.get_mut("a").unwrap() += 1;
+    assert!(cache.insert("f".to_string(), 5));
+    assert!(!cache.contains_key("c"));
+    assert_eq!(cache.remove("a"), Some(11));
+    assert_eq!(cache.remove("a"), None);
+    assert_eq!(cache.len(), 3);
+    for key in ["g", "h", "i"] {
+        cache.insert(key.to_string(), 0);
+    }
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache.get("i"), Some(&0));
+}
+
+#[test]
+fn test_replace_key() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    cache.get(&"b");
+    assert!(cache.replace_key(&"b", "B"));
+    assert!(!cache.replace_key(&"b", "x"));
+    assert!(!cache.replace_key(&"a", "c"));
+    cache.check_consistency();
+    assert_eq!(
+        entries(&cache),
+        vec![("c", 3, false), ("B", 2, true), ("a", 1, false)]
+    );
+    cache.insert("d", 4);
+    cache.insert("e", 5);
+    assert_eq!(cache.order_string(), "e -> d -> B (hand@d)");
+}
+
+#[test]
+fn test_stats_sampling() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut cache = SieveCache::with_stats_sampling(100, 16).unwrap();
+    let mut hits = 0;
+    for _ in 0..100_000 {
+        let key = rng.gen_range(0..200u32);
+        if cache.get(&key).is_some() {
+            hits += 1;
+        } else {
+            cache.insert(key, key);
+        }
+    }
+    let stats = cache.stats();
+    assert_eq!(stats.hits % 16, 0);
+    assert!((stats.hits as f64 - hits as f64).abs() < hits as f64 * 0.02);
+    assert!(stats.hits + stats.misses <= 100_000);
+    assert!(SieveCache::<u32, u32>::with_stats_sampling(100, 0).is_err());
+}
+
+#[test]
+fn test_into_sorted_vec() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for key in ["d", "b", "e", "a", "c"] {
+        cache.insert(key, key.len());
+    }
+    let sorted = cache.into_sorted_vec();
+    let keys: Vec<_> = sorted.iter().map(|(k, _)| *k).collect();
+    assert_eq!(keys, vec!["a", "b", "c", "e"]);
+}
+
+#[test]
+fn test_update_hand_entry() {
+    let mut cache = SieveCache::new(3).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        cache.insert(key, 0);
+    }
+    assert_eq!(cache.order_string(), "d -> c -> b (hand@b)");
+    assert_eq!(cache.insert("b", 1), (false, true));
+    assert_eq!(cache.order_string(), "d -> c -> b* (hand@b)");
+    cache.insert("e", 0);
+    cache.check_consistency();
+    assert_eq!(cache.order_string(), "e -> d -> b (hand@d)");
+
+    // The hand moves past the updated entry once its flag is cleared, then wraps around.
+    cache.insert("d", 2);
+    cache.insert("f", 0);
+    cache.check_consistency();
+    assert_eq!(cache.order_string(), "f -> d -> b");
+    cache.insert("g", 0);
+    cache.check_consistency();
+    assert_eq!(cache.order_string(), "g -> f -> d (hand@d)");
+    assert_eq!(cache.get(&"d"), Some(&2));
+
+    // Under LRU, updating the entry under the hand relinks it without invalidating the hand.
+    let mut cache = SieveCache::with_policy(2, Policy::Lru).unwrap();
+    for key in ["a", "b", "c"] {
+        cache.insert(key, 0);
+    }
+    let hand = cache.hand.map(|node| unsafe { node.as_ref().key });
+    cache.insert(hand.unwrap(), 1);
+    cache.check_consistency();
+    cache.insert("d", 0);
+    cache.check_consistency();
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn test_shrink_map_to() {
+    let mut cache = SieveCache::new(1000).unwrap();
+    for i in 0..10 {
+        cache.insert(i, i);
+    }
+    cache.shrink_map_to(100);
+    assert!(cache.map_capacity() >= 100 && cache.map_capacity() < 1000);
+    cache.shrink_map_to(0);
+    assert!(cache.map_capacity() >= 10);
+    assert_eq!(cache.capacity(), 1000);
+    for i in 0..10 {
+        assert_eq!(cache.get(&i), Some(&i));
+    }
+    cache.check_consistency();
+}
+
+#[test]
+fn test_evict_condition_ext() {
+    fn evict_cold(_: &&str, _: &u32, visited: bool) -> bool {
+        !visited
+    }
+
+    let mut plain = SieveCache::with_max_scan(3, 1).unwrap();
+    let mut cache = SieveCache::with_evict_condition_ext(3, evict_cold).unwrap();
+    cache.set_max_scan(1);
+    for cache in [&mut plain, &mut cache] {
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("c", 3);
+        cache.get(&"a");
+        cache.get(&"b");
+    }
+    // Past max_scan, SIEVE evicts the visited "b", unless the condition keeps visited entries.
+    assert_eq!(plain.peek_victims(1), vec![(&"b", &2)]);
+    assert_eq!(cache.peek_victims(1), vec![(&"c", &3)]);
+    cache.insert("d", 4);
+    assert!(!cache.contains_key(&"c"));
+    assert_eq!(cache.visited(&"b"), Some(false));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_serialize_top() {
+    let mut cache = SieveCache::new(5).unwrap();
+    for i in 0..5 {
+        cache.insert(i, i * 10);
+    }
+    let top = cache.serialize_top(2);
+    assert_eq!(top, vec![(4, 40), (3, 30)]);
+    assert_eq!(cache.serialize_top(10).len(), 5);
+
+    let restored = SieveCache::from_pairs(5, top).unwrap();
+    assert_eq!(restored.serialize_top(5), vec![(4, 40), (3, 30)]);
+    restored.check_consistency();
+}
+
+#[test]
+fn test_get_with() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", "hello".to_string());
+    assert_eq!(cache.get_with("a", |v| v.len()), Some(5));
+    assert_eq!(cache.get_with("b", |v| v.len()), None);
+    assert_eq!(cache.visited("a"), Some(true));
+}
+
+#[test]
+fn test_peek_with() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", vec![1, 2, 3]);
+    assert_eq!(cache.peek_with("a", |v| v.iter().sum::<i32>()), Some(6));
+    assert_eq!(cache.peek_with("b", |v| v.len()), None);
+    assert_eq!(cache.visited("a"), Some(false));
+    assert_eq!(cache.stats().hits + cache.stats().misses, 0);
+}
+
+#[test]
+fn test_remove_matching_first() {
+    let mut cache = SieveCache::new(5).unwrap();
+    for (key, value) in [("a", 5), ("b", 20), ("c", 1), ("d", 30), ("e", 2)] {
+        cache.insert(key, value);
+    }
+    assert_eq!(cache.remove_matching_first(|_, &v| v > 10), Some(("d", 30)));
+    assert_eq!(cache.remove_matching_first(|_, &v| v > 100), None);
+    cache.check_consistency();
+    assert_eq!(
+        entries(&cache),
+        vec![
+            ("e", 2, false),
+            ("c", 1, false),
+            ("b", 20, false),
+            ("a", 5, false)
+        ]
+    );
+}
+
+#[test]
+fn test_scan_direction() {
+    let mut caches = [ScanDirection::TailToHead, ScanDirection::HeadToTail]
+        .map(|direction| SieveCache::with_scan_direction(4, direction).unwrap());
+    for cache in &mut caches {
+        for key in ["a", "b", "c", "d"] {
+            cache.insert(key, 0);
+        }
+        cache.get(&"a");
+        cache.get(&"d");
+    }
+    let [tail_to_head, head_to_tail] = &mut caches;
+    assert_eq!(tail_to_head.first_unvisited(), Some((&"b", &0)));
+    assert_eq!(head_to_tail.first_unvisited(), Some((&"c", &0)));
+    assert_eq!(head_to_tail.peek_victims(2), vec![(&"c", &0), (&"b", &0)]);
+    tail_to_head.insert("e", 0);
+    head_to_tail.insert("e", 0);
+    assert_eq!(tail_to_head.order_string(), "e -> d* -> c -> a (hand@c)");
+    assert_eq!(head_to_tail.order_string(), "e -> d -> b -> a* (hand@b)");
+    for cache in &mut caches {
+        cache.insert("f", 0);
+        cache.check_consistency();
+    }
+    let [tail_to_head, head_to_tail] = &caches;
+    assert!(!tail_to_head.contains_keys([&"b", &"c"]));
+    assert!(!head_to_tail.contains_keys([&"b", &"c"]));
+}
+
+#[test]
+fn test_update_or_insert_with() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", 1);
+    assert_eq!(
+        cache.update_or_insert_with("a", |v| *v += 1, || 0),
+        Some(&2)
+    );
+    assert_eq!(
+        cache.update_or_insert_with("b", |v| *v += 1, || 1),
+        Some(&1)
+    );
+    assert_eq!(cache.visited(&"a"), Some(true));
+    assert_eq!(cache.visited(&"b"), Some(false));
 }
 
 #[test]
-fn test_visited_flag_update() {
+fn test_update() {
     let mut cache = SieveCache::new(2).unwrap();
-    cache.insert("key1".to_string(), "value1".to_string());
-    cache.insert("key2".to_string(), "value2".to_string());
-    // update `key1` entry.
-    cache.insert("key1".to_string(), "updated".to_string());
-    // new entry is added.
-    cache.insert("key3".to_string(), "value3".to_string());
-    assert_eq!(cache.get("key1"), Some(&"updated".to_string()));
+    cache.insert("a", vec![1]);
+    assert!(cache.update("a", |v| v.push(2)));
+    assert!(!cache.update("b", |v| v.push(2)));
+    assert_eq!(cache.get("a"), Some(&vec![1, 2]));
+    assert!(!cache.contains_key("b"));
+    assert_eq!(cache.len(), 1);
 }
 
-fn evict_string_cond(k: &String, v: &String) -> bool {
-    dbg!(v);
-    return v.len() < 6;
+#[test]
+fn test_read_only_accessors_share_borrows() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    let mut iter = cache.iter();
+    assert_eq!(iter.next(), Some((&"b", &2)));
+    assert!(cache.contains_key(&"a"));
+    assert_eq!(cache.peek(&"a"), Some(&1));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(iter.next(), Some((&"a", &1)));
+    assert_eq!(cache.keys().collect::<Vec<_>>(), vec![&"b", &"a"]);
+    assert_eq!(cache.values().rev().collect::<Vec<_>>(), vec![&1, &2]);
+    assert_eq!(cache.visited(&"a"), Some(false));
 }
 
 #[test]
-fn test_with_eviction() {
-    let mut cache = SieveCache::with_evict_condition(3, evict_string_cond).unwrap();
-    assert!(cache.insert("a".to_string(), "aaaaaa".to_string()).0);
-    assert!(cache.insert("b".to_string(), "bbbbbb".to_string()).0);
-    assert!(cache.insert("c".to_string(), "cccccc".to_string()).0);
-    assert!(cache.insert("bar".to_string(), "barc".to_string()).1 == false);
-    assert_eq!(cache.get("a"), Some(&"aaaaaa".to_string()));
-    assert_eq!(cache.get("b"), Some(&"bbbbbb".to_string()));
-    assert_eq!(cache.get("c"), Some(&"cccccc".to_string()));
-    assert_eq!(cache.get("bar"), None);
+fn test_with_capacity_bytes() {
+    let mut cache: SieveCache<u32, Vec<u8>> =
+        SieveCache::with_capacity_bytes(1024, |_, v: &Vec<u8>| v.len()).unwrap();
+    for i in 0..100 {
+        cache.insert(i, vec![0; 10 + (i as usize * 37) % 200]);
+        assert!(cache.weight() <= 1024);
+    }
+    let bytes: usize = cache.values().map(Vec::len).sum();
+    assert_eq!(bytes, cache.weight());
+    assert!(cache.len() > 5);
+    assert_eq!(
+        SieveCache::<u32, Vec<u8>>::with_capacity_bytes(0, |_, v| v.len()).err(),
+        Some(CacheError::ZeroCapacity)
+    );
 }
 
 #[test]
-fn test_with_eviction_2() {
-    let mut cache = SieveCache::with_evict_condition(3, evict_string_cond).unwrap();
-    assert!(cache.insert("a".to_string(), "aaaaaa".to_string()).0);
-    assert!(cache.insert("b".to_string(), "bbbbbb".to_string()).0);
-    assert!(cache.insert("c".to_string(), "c".to_string()).0);
-    assert!(cache.insert("bar".to_string(), "barc".to_string()).1 == true);
-    assert_eq!(cache.get("a"), Some(&"aaaaaa".to_string()));
-    assert_eq!(cache.get("b"), Some(&"bbbbbb".to_string()));
-    assert_eq!(cache.get("bar"), Some(&"barc".to_string()));
-    assert_eq!(cache.get("c"), None);
+fn test_clear_and_resize() {
+    let mut cache = SieveCache::new(1000).unwrap();
+    for i in 0..1000 {
+        cache.insert(i, i);
+    }
+    assert_eq!(cache.clear_and_resize(0), Err(CacheError::ZeroCapacity));
+    assert_eq!(cache.len(), 1000);
+    cache.clear_and_resize(10).unwrap();
+    assert!(cache.is_empty());
+    assert_eq!(cache.capacity(), 10);
+    assert!(cache.map_capacity() >= 10 && cache.map_capacity() < 1000);
+    cache.check_consistency();
+    for i in 0..20 {
+        cache.insert(i, i);
+    }
+    assert_eq!(cache.len(), 10);
+    cache.clear_and_resize(5000).unwrap();
+    assert!(cache.map_capacity() >= 5000);
+    cache.insert(1, 1);
+    assert_eq!(cache.get(&1), Some(&1));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_rank() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        cache.insert(key, 0);
+    }
+    let ranks: Vec<_> = ["a", "b", "c", "d"].iter().map(|k| cache.rank(k)).collect();
+    assert_eq!(ranks, vec![Some(0), Some(1), Some(2), Some(3)]);
+    assert_eq!(cache.rank(&"x"), None);
+
+    // After evicting "a", the hand sits on "b" and the new head comes last.
+    cache.insert("e", 0);
+    let ranks: Vec<_> = ["b", "c", "d", "e"].iter().map(|k| cache.rank(k)).collect();
+    assert_eq!(ranks, vec![Some(0), Some(1), Some(2), Some(3)]);
+    cache.get(&"b");
+    cache.insert("f", 0);
+    let ranks: Vec<_> = ["d", "e", "f", "b"].iter().map(|k| cache.rank(k)).collect();
+    assert_eq!(ranks, vec![Some(0), Some(1), Some(2), Some(3)]);
+}
+
+#[test]
+fn test_insert_full() {
+    let mut cache = SieveCache::with_evict_condition(2, evict_string_cond).unwrap();
+    let key = |k: &str| k.to_string();
+    assert_eq!(
+        cache.insert_full(key("a"), key("1")),
+        Ok((InsertOutcome::Inserted, None))
+    );
+    assert_eq!(
+        cache.insert_full(key("b"), key("22")),
+        Ok((InsertOutcome::Inserted, None))
+    );
+    assert_eq!(
+        cache.insert_full(key("a"), key("long value")),
+        Ok((InsertOutcome::Updated, None))
+    );
+    assert_eq!(
+        cache.insert_full(key("c"), key("long value")),
+        Ok((InsertOutcome::Inserted, Some((key("b"), key("22")))))
+    );
+    // Neither remaining value is short enough to be evicted.
+    assert_eq!(
+        cache.insert_full(key("d"), key("4")),
+        Err((key("d"), key("4")))
+    );
+    assert_eq!(cache.len(), 2);
+    cache.check_consistency();
+}
+
+#[test]
+fn test_growth_policy() {
+    let mut cache = SieveCache::with_growth_policy(4, 20, 2.0).unwrap();
+    let mut capacities = vec![cache.capacity()];
+    for i in 0..30 {
+        cache.insert(i, i);
+        if cache.capacity() != *capacities.last().unwrap() {
+            capacities.push(cache.capacity());
+        }
+        cache.check_consistency();
+    }
+    assert_eq!(capacities, vec![4, 8, 16, 20]);
+    assert_eq!(cache.len(), 20);
+    assert_eq!(cache.stats().evictions, 10);
+    assert!(!cache.contains_key(&0));
+    assert!(SieveCache::<u32, u32>::with_growth_policy(4, 2, 2.0).is_err());
+    assert!(SieveCache::<u32, u32>::with_growth_policy(4, 8, 1.0).is_err());
+}
+
+#[test]
+fn test_dedup_values() {
+    let mut cache = SieveCache::new(8).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 1);
+    cache.insert("d", 1);
+    cache.insert("e", 2);
+    cache.insert("f", 3);
+    assert_eq!(cache.dedup_values(), 3);
+    cache.check_consistency();
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["f", "e", "d"]);
+    assert_eq!(cache.dedup_values(), 0);
+}
+
+#[test]
+fn test_into_iter_with_meta_round_trip() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for i in 0..6 {
+        cache.insert(i, i.to_string());
+        if i % 2 == 0 {
+            cache.get(&i);
+        }
+    }
+    let expected = entries(&cache);
+    let mut rebuilt = SieveCache::new(4).unwrap();
+    rebuilt.append_in_order(cache.into_iter_with_meta());
+    rebuilt.check_consistency();
+    assert_eq!(entries(&rebuilt), expected);
+}
+
+#[test]
+fn test_get_or_insert_with_skips_doomed_loads() {
+    let mut cache = SieveCache::with_evict_condition(2, |_: &u32, _: &u32| false).unwrap();
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    let doomed = || -> u32 { panic!("the value can't be inserted") };
+    assert_eq!(cache.get_or_insert_with(3, doomed), None);
+    assert_eq!(
+        cache.get_or_insert_checked(3, doomed),
+        Err(CacheError::EvictionBlocked)
+    );
+    assert_eq!(cache.entry_ref(&3).or_insert_with(doomed), None);
+    assert_eq!(cache.update_or_insert_with(3, |_| (), doomed), None);
+    assert_eq!(cache.get_many_or_insert_with(vec![3], |_| doomed()), [None]);
+    assert_eq!(cache.get_or_insert_with(1, doomed), Some(&1));
+    assert_eq!(cache.len(), 2);
+    cache.check_consistency();
+
+    // A weighted cache is full once its weight is, however few entries it holds
+    let mut cache = SieveCache::with_weigher(10, |_: &u32, value: &u32| *value as usize).unwrap();
+    cache.set_evict_condition(|_, _| false);
+    cache.insert(1, 10);
+    assert_eq!(cache.get_or_insert_with(2, doomed), None);
+    assert_eq!(cache.stats().insert_rejections, 1);
+}
+
+#[test]
+fn test_reset() {
+    let mut cache = SieveCache::with_evict_condition(3, |key: &u32, _: &u32| *key != 0).unwrap();
+    cache.set_evict_listener(Box::new(|_, _| {}));
+    for i in 0..5 {
+        cache.insert(i, i);
+        cache.get(&i);
+    }
+    cache.reset();
+    assert!(cache.is_empty());
+    assert_eq!(cache.stats(), CacheStats::default());
+    assert_eq!(cache.capacity(), 3);
+    assert!(cache.take_evict_listener().is_some());
+    for i in 0..5 {
+        cache.insert(i, i);
+    }
+    assert!(cache.contains_key(&0));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_get_cloned() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", vec![1]);
+    let mut value = cache.get_cloned("a").unwrap();
+    value.push(2);
+    assert_eq!(cache.peek("a"), Some(&vec![1]));
+    assert_eq!(cache.visited("a"), Some(true));
+    assert_eq!(cache.get_cloned("b"), None);
+}
+
+#[test]
+fn test_contains_or_insert() {
+    let mut cache = SieveCache::new(2).unwrap();
+    assert!(!cache.contains_or_insert("a", 1));
+    assert_eq!(cache.visited("a"), Some(false));
+    assert!(cache.contains_or_insert("a", 2));
+    assert_eq!(cache.peek("a"), Some(&1));
+    assert_eq!(cache.visited("a"), Some(true));
+    assert!(!cache.contains_or_insert("b", 3));
+    assert_eq!(cache.len(), 2);
+    cache.check_consistency();
+}
+
+#[test]
+fn test_extend_from_references() {
+    let mut source = SieveCache::new(3).unwrap();
+    for i in 0..3 {
+        source.insert(i, i * 10);
+    }
+    let mut cache = SieveCache::new(4).unwrap();
+    cache.insert(7, 70);
+    cache.extend(source.iter());
+    cache.check_consistency();
+    assert_eq!(cache.len(), 4);
+    for i in 0..3 {
+        assert_eq!(cache.peek(&i), Some(&(i * 10)));
+    }
+    cache.extend([(8, 80)]);
+    assert_eq!(cache.peek(&8), Some(&80));
+}
+
+#[test]
+fn test_swap() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    assert!(cache.swap("a", "c"));
+    assert_eq!(
+        entries(&cache),
+        [("c", 1, true), ("b", 2, false), ("a", 3, true)]
+    );
+    assert!(!cache.swap("a", "a"));
+    assert!(!cache.swap("a", "z"));
+    assert_eq!(cache.peek("a"), Some(&3));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_front_and_back() {
+    let mut cache = SieveCache::new(3).unwrap();
+    assert_eq!(cache.front(), None);
+    assert_eq!(cache.back(), None);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    assert_eq!(cache.front(), Some((&"c", &3)));
+    assert_eq!(cache.back(), Some((&"a", &1)));
+    assert_eq!(cache.newest(), cache.front());
+    assert_eq!(cache.oldest(), cache.back());
+    assert_eq!(cache.visited("a"), Some(false));
+}
+
+#[test]
+fn test_evict_one() {
+    let mut cache = SieveCache::new(3).unwrap();
+    assert!(!cache.evict_one());
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    cache.get("a");
+    assert!(cache.evict_one());
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["c", "a"]);
+    assert_eq!(cache.stats().evictions, 1);
+    cache.check_consistency();
+
+    let mut blocked = SieveCache::with_evict_condition(2, |_: &&str, _: &u32| false).unwrap();
+    blocked.insert("a", 1);
+    assert!(!blocked.evict_one());
+    assert_eq!(blocked.len(), 1);
+}
+
+#[test]
+fn test_try_set_capacity() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for i in 0..4 {
+        cache.insert(i, i);
+    }
+    assert_eq!(
+        cache.try_set_capacity(usize::MAX),
+        Err(CacheError::CapacityOverflow)
+    );
+    assert_eq!(cache.capacity(), 4);
+    assert_eq!(cache.try_set_capacity(0), Err(CacheError::ZeroCapacity));
+    cache.try_set_capacity(100).unwrap();
+    assert!(cache.map_capacity() >= 100);
+    cache.try_set_capacity(2).unwrap();
+    assert_eq!(cache.len(), 2);
+    cache.check_consistency();
+}
+
+#[test]
+fn test_get_or_insert_reporting() {
+    let mut cache = SieveCache::new(2).unwrap();
+    assert_eq!(cache.get_or_insert_reporting("a", || 1), (Some(&1), false));
+    assert_eq!(cache.get_or_insert_reporting("b", || 2), (Some(&2), false));
+    assert_eq!(cache.get_or_insert_reporting("c", || 3), (Some(&3), true));
+    assert_eq!(cache.get_or_insert_reporting("c", || 4), (Some(&3), false));
+    assert!(!cache.contains_key("a"));
+
+    let mut blocked = SieveCache::with_evict_condition(1, |_: &&str, _: &u32| false).unwrap();
+    blocked.insert("a", 1);
+    assert_eq!(blocked.get_or_insert_reporting("b", || 2), (None, false));
+}
+
+#[test]
+fn test_decay_visited() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for i in 0..4 {
+        cache.insert(i, i);
+        cache.get(&i);
+    }
+    cache.decay_visited();
+    assert!((0..4).all(|i| cache.visited(&i) == Some(true)));
+    cache.get(&1);
+    *cache.access_mut(&2).unwrap() += 1;
+    cache.decay_visited();
+    assert_eq!(
+        (0..4)
+            .map(|i| cache.visited(&i).unwrap())
+            .collect::<Vec<_>>(),
+        [false, true, true, false]
+    );
+    cache.decay_visited();
+    assert!((0..4).all(|i| cache.visited(&i) == Some(false)));
+}
+
+#[test]
+fn test_contains_each() {
+    let mut cache = SieveCache::new(4).unwrap();
+    cache.insert(1, ());
+    cache.insert(3, ());
+    assert_eq!(
+        cache.contains_each(&[1, 2, 3, 4, 1]),
+        [true, false, true, false, true]
+    );
+    assert!(cache.contains_each(&[]).is_empty());
+}
+
+#[test]
+fn test_pressure() {
+    let mut cache = SieveCache::new(8).unwrap();
+    let mut pressures = vec![cache.pressure()];
+    for i in 0..8 {
+        cache.insert(i, i);
+        pressures.push(cache.pressure());
+    }
+    assert!(pressures.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(pressures[0], 0.0);
+    assert_eq!(pressures[8], 8.0);
+
+    // A weighted cache is as saturated as its weight, however few entries it holds
+    let mut cache = SieveCache::with_weigher(100, |_: &u32, value: &usize| *value).unwrap();
+    cache.insert(0, 50);
+    assert_eq!(cache.pressure(), 50.0 / 51.0);
+    cache.insert(1, 50);
+    assert_eq!(cache.pressure(), 100.0);
+}
+
+#[test]
+fn test_relinking_never_creates_self_loops() {
+    let mut cache = SieveCache::with_policy(3, Policy::Lru).unwrap();
+    cache.insert("a", 1);
+    // Accessing the only entry, which is both the head and the tail
+    cache.get("a");
+    cache.insert("b", 2);
+    cache.get("b");
+    cache.get("b");
+    cache.get("a");
+    assert!(cache.replace_key(&"a", "c"));
+    cache.get("c");
+    cache.insert("d", 4);
+    assert!(cache.swap("b", "d"));
+    cache.insert("e", 5);
+    cache.check_consistency();
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["e", "d", "b"]);
+}
+
+#[test]
+fn test_with_nonzero_capacity() {
+    let mut cache: SieveCache<u32, u32> =
+        SieveCache::with_nonzero_capacity(NonZeroUsize::new(8).unwrap());
+    assert_eq!(cache.capacity(), 8);
+    cache.insert(1, 1);
+    assert_eq!(cache.get(&1), Some(&1));
+}
+
+#[cfg(test)]
+static CONDITION_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[test]
+fn test_evict_condition_only_consulted_for_unvisited_entries() {
+    use std::sync::atomic::Ordering;
+
+    let mut cache = SieveCache::with_evict_condition(4, |key: &u32, _: &u32| {
+        CONDITION_CALLS.fetch_add(1, Ordering::Relaxed);
+        *key != 2
+    })
+    .unwrap();
+    for i in 1..=4 {
+        cache.insert(i, i);
+    }
+    cache.get(&1);
+    cache.get(&3);
+    CONDITION_CALLS.store(0, Ordering::Relaxed);
+    // 1: visited, allowed; 2: unvisited, blocked; 3: visited, allowed; 4: unvisited, allowed
+    cache.insert(5, 5);
+    assert!(!cache.contains_key(&4));
+    assert_eq!(CONDITION_CALLS.load(Ordering::Relaxed), 2);
+    assert_eq!(cache.visited(&1), Some(false));
+    assert_eq!(cache.visited(&3), Some(false));
+    cache.insert(6, 6);
+    assert!(!cache.contains_key(&1));
+    cache.check_consistency();
+
+    // A visited entry that the condition blocks just loses its flag
+    let mut cache = SieveCache::with_evict_condition(2, |key: &u32, _: &u32| *key != 1).unwrap();
+    cache.insert(1, 1);
+    cache.insert(2, 2);
+    cache.get(&1);
+    cache.insert(3, 3);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [3, 1]);
+    assert_eq!(cache.visited(&1), Some(false));
+}
+
+#[test]
+fn test_take() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    let value = cache.take("a").unwrap();
+    assert_eq!(cache.take("a"), None);
+    assert_eq!(cache.len(), 2);
+    cache.insert("a", value + 1);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["a", "c", "b"]);
+    assert_eq!(cache.peek("a"), Some(&2));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_condition_stats() {
+    let mut cache = SieveCache::with_evict_condition(3, |key: &u32, _: &u32| *key >= 10).unwrap();
+    for i in 0..3 {
+        cache.insert(i, i);
+    }
+    // Every entry is vetoed once before the sweep gives up
+    assert_eq!(cache.insert(3, 3), (false, false));
+    assert_eq!(cache.stats().condition_vetoes, 3);
+    assert_eq!(cache.stats().insert_rejections, 1);
+    assert_eq!(cache.get_or_insert_with(4, || 4), None);
+    assert_eq!(cache.stats().insert_rejections, 2);
+    assert_eq!(cache.stats().evictions, 0);
+}
+
+#[test]
+fn test_with_initial_entries() {
+    let cache = SieveCache::with_initial_entries(4, (0..10).map(|i| (i, i))).unwrap();
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [9, 8, 7, 6]);
+    cache.check_consistency();
+    assert_eq!(
+        SieveCache::<u32, u32>::with_initial_entries(0, []).err(),
+        Some(CacheError::ZeroCapacity)
+    );
+}
+
+#[test]
+fn test_as_refs() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    let refs = cache.as_refs();
+    assert_eq!(refs, [(&"a", &1), (&"b", &2), (&"c", &3)]);
+    assert_eq!(cache.visited("a"), Some(false));
+}
+
+#[test]
+fn test_flush_to() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for i in 0..4 {
+        cache.insert(i, i * 10);
+    }
+    let mut flushed = Vec::new();
+    cache.flush_to(|key, value| flushed.push((key, value)));
+    assert_eq!(flushed, [(0, 0), (1, 10), (2, 20), (3, 30)]);
+    assert!(cache.is_empty());
+    cache.check_consistency();
+
+    for i in 0..4 {
+        cache.insert(i, i);
+    }
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        cache.flush_to(|key, _| assert!(key < 2))
+    }));
+    assert!(result.is_err());
+    cache.check_consistency();
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [3]);
+}
+
+#[test]
+fn test_default_cap_sieve_cache() {
+    #[derive(Default)]
+    struct Service {
+        cache: DefaultCapSieveCache<u32, String, 2>,
+    }
+
+    let mut service = Service::default();
+    assert_eq!(service.cache.capacity(), 2);
+    for i in 0..3 {
+        service.cache.insert(i, i.to_string());
+    }
+    assert_eq!(service.cache.len(), 2);
+    assert_eq!(service.cache.get(&2).map(String::as_str), Some("2"));
+    assert_eq!(service.cache.into_inner().len(), 2);
+}
+
+#[test]
+fn test_metadata() {
+    let mut cache = SieveCache::with_weigher(100, |_: &&str, value: &usize| *value).unwrap();
+    cache.insert("a", 10);
+    cache.insert("b", 20);
+    cache.insert("c", 30);
+    cache.get("b");
+    assert_eq!(
+        cache.metadata("b"),
+        Some(EntryMeta {
+            visited: true,
+            rank: 1,
+            weight: 20,
+        })
+    );
+    assert_eq!(cache.metadata("a").map(|meta| meta.rank), Some(0));
+    assert_eq!(cache.metadata("z"), None);
+    assert_eq!(cache.visited("a"), Some(false));
+}
+
+#[test]
+fn test_zero_weights_count_as_one() {
+    let mut cache = SieveCache::with_weigher(10, |_: &u32, _: &()| 0).unwrap();
+    for i in 0..1000 {
+        cache.insert(i, ());
+    }
+    assert_eq!(cache.len(), 10);
+    assert_eq!(cache.weight(), 10);
+    assert_eq!(cache.metadata(&999).map(|meta| meta.weight), Some(1));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_setters_compose() {
+    let mut cache: SieveCache<u32, u32, _> = SieveCache::with_hasher(
+        4,
+        std::hash::BuildHasherDefault::<std::collections::hash_map::DefaultHasher>::default(),
+    )
+    .unwrap();
+    cache.set_policy(Policy::Fifo);
+    cache.set_evict_condition(|_, value| value % 2 == 0);
+    cache.set_scan_direction(ScanDirection::HeadToTail);
+    cache.set_stats_sampling(2).unwrap();
+    cache.set_growth_policy(6, 1.5).unwrap();
+    assert!(cache.set_stats_sampling(0).is_err());
+    assert!(cache.set_growth_policy(2, 2.0).is_err());
+    assert!(cache.set_weigher(10, |_, _| 1).is_err());
+    assert_eq!(cache.eviction_policy_name(), "FIFO");
+    for i in 0..8 {
+        cache.insert(i, i);
+    }
+    // Grown to 6 entries, then the hand moves from the head and only evicts even values
+    assert_eq!(cache.capacity(), 6);
+    assert_eq!(
+        cache.keys().copied().collect::<Vec<_>>(),
+        [7, 6, 5, 3, 1, 0]
+    );
+    cache.check_consistency();
+
+    let mut cache = SieveCache::new(10).unwrap();
+    for i in 0..6 {
+        cache.insert(i, i as usize);
+    }
+    cache
+        .set_weigher(10, |_: &u32, value: &usize| *value)
+        .unwrap();
+    assert_eq!((cache.capacity(), cache.max_weight()), (10, 10));
+    assert!(cache.weight() <= 10);
+    assert!(cache.set_growth_policy(20, 2.0).is_err());
+    cache.check_consistency();
+}
+
+#[test]
+fn test_overweight_update_removes_entry() {
+    let mut cache = SieveCache::with_weigher(10, |_: &u32, value: &usize| *value).unwrap();
+    for i in 1..=3 {
+        cache.insert(i, 3);
+    }
+    assert_eq!(cache.insert_result(2, 50), InsertOutcome::Rejected);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [3, 1]);
+    assert_eq!(cache.weight(), 6);
+
+    assert!(!cache.compare_and_update(&1, &3, 11));
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [3]);
+    assert!(cache.compare_and_update(&3, &3, 4));
+
+    cache.insert(1, 3);
+    cache.append_in_order([(3, 20, true), (1, 2, true)]);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), [1]);
+    assert_eq!(cache.weight(), 2);
+    assert_eq!(cache.visited(&1), Some(true));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_weighted_set_capacity() {
+    let mut cache = SieveCache::with_weigher(1 << 20, |_: &u32, value: &usize| *value).unwrap();
+    for i in 0..4 {
+        cache.insert(i, 100);
+    }
+    cache.set_capacity(250).unwrap();
+    assert_eq!((cache.capacity(), cache.max_weight()), (250, 250));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.weight() <= 250);
+    assert!(cache.map_capacity() < 1 << 10);
+
+    cache.try_set_capacity(1 << 30).unwrap();
+    assert_eq!(cache.max_weight(), 1 << 30);
+    assert!(cache.map_capacity() < 1 << 10);
+    assert_eq!(cache.try_set_capacity(100), Ok(()));
+    assert_eq!(cache.len(), 1);
+
+    cache.clear_and_resize(1 << 30).unwrap();
+    assert_eq!(cache.max_weight(), 1 << 30);
+    assert!(cache.map_capacity() < 1 << 10);
+    cache.check_consistency();
+}
+
+#[test]
+fn test_clear_with() {
+    let mut cache = SieveCache::new(1000).unwrap();
+    for i in 0..1000 {
+        cache.insert(i, i);
+    }
+    let map_capacity = cache.map_capacity();
+    cache.clear_with(true);
+    assert!(cache.is_empty());
+    assert_eq!(cache.map_capacity(), map_capacity);
+    for i in 0..1000 {
+        cache.insert(i, i);
+    }
+    cache.clear_with(false);
+    assert!(cache.is_empty());
+    assert!(cache.map_capacity() < map_capacity);
+    cache.insert(1, 1);
+    assert_eq!(cache.get(&1), Some(&1));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_compact_values() {
+    let mut cache = SieveCache::new(4).unwrap();
+    for i in 0..4 {
+        let mut value = String::with_capacity(1024);
+        value.push_str(&i.to_string());
+        cache.insert(i, value);
+    }
+    cache.compact_values(String::shrink_to_fit);
+    for i in 0..4 {
+        let value = cache.peek(&i).unwrap();
+        assert_eq!(*value, i.to_string());
+        assert!(value.capacity() < 1024);
+        assert_eq!(cache.visited(&i), Some(false));
+    }
+}
+
+#[test]
+fn test_sample_hot_and_cold() {
+    let mut cache = SieveCache::new(6).unwrap();
+    for i in 0..6 {
+        cache.insert(i, i);
+    }
+    cache.get(&1);
+    cache.get(&3);
+    cache.get(&4);
+    assert_eq!(cache.sample_hot(10), [&4, &3, &1]);
+    assert_eq!(cache.sample_hot(2), [&4, &3]);
+    assert_eq!(cache.sample_cold(10), [&5, &2, &0]);
+    assert!(cache.sample_cold(0).is_empty());
+    assert_eq!(cache.visited(&1), Some(true));
+}
+
+#[test]
+fn test_summary() {
+    let mut cache = SieveCache::new(4).unwrap();
+    assert_eq!(cache.summary().to_string(), "SieveCache[0/4, 0 evicted]");
+    for i in 0..6 {
+        cache.insert(i, i);
+    }
+    for i in 0..4 {
+        cache.get(&i);
+    }
+    let summary = cache.summary();
+    assert_eq!(summary.len, 4);
+    assert_eq!(summary.utilization, 1.0);
+    assert_eq!(summary.eviction_count, 2);
+    assert_eq!(summary.hit_ratio, Some(0.5));
+    assert_eq!(summary.to_string(), "SieveCache[4/4, 50% hit, 2 evicted]");
+
+    let mut cache = SieveCache::with_weigher(100, |_: &u32, value: &usize| *value).unwrap();
+    cache.insert(0, 25);
+    assert_eq!(cache.summary().utilization, 0.25);
+}
+
+#[test]
+fn test_promote() {
+    let mut cache = SieveCache::new(3).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.insert("c", 3);
+    assert!(cache.promote("a"));
+    assert!(cache.promote("a"));
+    assert!(!cache.promote("z"));
+    assert_eq!(cache.front(), Some((&"a", &1)));
+    assert_eq!(cache.visited("a"), Some(true));
+    cache.check_consistency();
+    cache.insert("d", 4);
+    assert_eq!(cache.keys().copied().collect::<Vec<_>>(), ["d", "a", "c"]);
+    assert_eq!(cache.stats().hits, 0);
+
+    // A promoted entry counts as active, so decaying keeps its flag
+    cache.decay_visited();
+    assert!(cache.promote("c"));
+    cache.decay_visited();
+    assert_eq!(cache.visited("c"), Some(true));
+    assert_eq!(cache.visited("a"), Some(false));
+
+    // The eviction policy sees the promotion as an access
+    #[derive(Clone)]
+    struct CountAccesses(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl<K: Eq + Hash + Clone, V> EvictionPolicy<K, V> for CountAccesses {
+        fn name(&self) -> &'static str {
+            "count"
+        }
+
+        fn on_access(&mut self, _node: NodeRef<'_, K, V>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+
+        fn select_victim<'a>(&mut self, list: &NodeList<'a, K, V>) -> Option<NodeRef<'a, K, V>> {
+            list.tail()
+        }
+    }
+
+    let accesses = std::sync::Arc::default();
+    let mut cache =
+        SieveCache::with_eviction_policy(2, CountAccesses(std::sync::Arc::clone(&accesses)))
+            .unwrap();
+    cache.insert(1, ());
+    cache.insert(2, ());
+    assert!(cache.promote(&1));
+    assert_eq!(accesses.load(std::sync::atomic::Ordering::Relaxed), 1);
+    assert_eq!(cache.front(), Some((&1, &())));
+    cache.check_consistency();
+}
+
+#[test]
+fn test_fully_visited_sweep_wraps_around() {
+    let mut cache = SieveCache::new(2).unwrap();
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    cache.get("a");
+    cache.get("b");
+    assert_eq!(cache.insert("c", 3), (true, true));
+    // Both flags were cleared, and the oldest entry, where the sweep started, was evicted
+    assert_eq!(cache.order_string(), "c -> b (hand@b)");
+    cache.get("c");
+    assert_eq!(cache.insert("d", 4), (true, true));
+    assert_eq!(cache.order_string(), "d -> c* (hand@c)");
+    cache.check_consistency();
+
+    let mut cache = SieveCache::new(3).unwrap();
+    for i in 0..3 {
+        cache.insert(i, i);
+        cache.get(&i);
+    }
+    let report = cache.evict_with_report();
+    assert_eq!(report.evicted, Some((0, 0)));
+    assert_eq!(report.nodes_scanned, 4);
+    assert_eq!(report.flags_cleared, 3);
+}
+
+#[cfg(test)]
+static BLOCK_EVICTIONS: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+#[test]
+fn test_hand_rests_in_place_after_failed_sweep() {
+    use std::sync::atomic::Ordering;
+
+    let mut cache = SieveCache::with_evict_condition(3, |_: &u32, _: &u32| {
+        !BLOCK_EVICTIONS.load(Ordering::Relaxed)
+    })
+    .unwrap();
+    for i in 1..=4 {
+        cache.insert(i, i);
+    }
+    for i in 2..=4 {
+        cache.get(&i);
+    }
+    assert_eq!(cache.order_string(), "4* -> 3* -> 2* (hand@2)");
+    BLOCK_EVICTIONS.store(true, Ordering::Relaxed);
+    assert_eq!(cache.insert(5, 5), (false, false));
+    assert_eq!(cache.order_string(), "4 -> 3 -> 2 (hand@2)");
+    BLOCK_EVICTIONS.store(false, Ordering::Relaxed);
+    cache.insert(6, 6);
+    assert_eq!(cache.order_string(), "6 -> 4 -> 3 (hand@3)");
+    cache.check_consistency();
+}
+
+#[test]
+fn test_get_many_or_insert_with() {
+    let mut cache = SieveCache::new(4).unwrap();
+    cache.insert(1, 10);
+    cache.insert(2, 20);
+    let mut loaded = Vec::new();
+    let values = cache.get_many_or_insert_with(vec![1, 3, 2, 4, 3], |key| {
+        loaded.push(*key);
+        key * 10
+    });
+    assert_eq!(
+        values,
+        [Some(&10), Some(&30), Some(&20), Some(&40), Some(&30)]
+    );
+    assert_eq!(loaded, [3, 4]);
+    assert_eq!(cache.visited(&1), Some(true));
+    cache.check_consistency();
+
+    let mut blocked = SieveCache::with_evict_condition(1, |_: &u32, _: &u32| false).unwrap();
+    blocked.insert(1, 1);
+    let values = blocked.get_many_or_insert_with(vec![2, 1], |_| panic!("can't be inserted"));
+    assert_eq!(values, [None, Some(&1)]);
+}
+
+#[test]
+fn test_send_and_sync() {
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    assert_send::<SieveCache<String, Vec<u8>>>();
+    assert_sync::<SyncSieveCache<String, Vec<u8>>>();
 }
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+/// A sink for the counters and gauges emitted by a cache, enabled by the `metrics` feature.
+///
+/// This mirrors the counter and gauge operations of metrics facades, so an adapter for one is a
+/// few lines long. See [`SieveCache::set_metrics_recorder`](crate::SieveCache::set_metrics_recorder)
+/// for the names of the emitted metrics.
+pub trait MetricsRecorder: Send + Sync {
+    /// Add `value` to the counter called `name`.
+    fn increment_counter(&self, name: &str, value: u64);
+
+    /// Set the gauge called `name` to `value`.
+    fn set_gauge(&self, name: &str, value: f64);
+}
+
+/// A recorder along with the full names of the metrics, built once from the prefix.
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    recorder: Arc<dyn MetricsRecorder>,
+    hits: Box<str>,
+    misses: Box<str>,
+    evictions: Box<str>,
+    len: Box<str>,
+}
+
+impl Metrics {
+    pub(crate) fn new(prefix: &str, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        Self {
+            recorder,
+            hits: format!("{prefix}hits").into(),
+            misses: format!("{prefix}misses").into(),
+            evictions: format!("{prefix}evictions").into(),
+            len: format!("{prefix}len").into(),
+        }
+    }
+
+    pub(crate) fn lookups(&self, hit: bool, count: u64) {
+        let name = if hit { &self.hits } else { &self.misses };
+        self.recorder.increment_counter(name, count);
+    }
+
+    pub(crate) fn eviction(&self) {
+        self.recorder.increment_counter(&self.evictions, 1);
+    }
+
+    pub(crate) fn len(&self, len: usize) {
+        self.recorder.set_gauge(&self.len, len as f64);
+    }
+}
@@ -0,0 +1,54 @@
+use std::hash::{BuildHasher, Hash};
+
+use arbitrary::Arbitrary;
+
+use crate::SieveCache;
+
+/// One operation on a cache, generated from fuzzer input with the `arbitrary` feature.
+///
+/// A fuzz target turns its input into a `Vec<Operation<K, V>>`, applies each operation with
+/// [`apply`](Self::apply), and checks the cache with
+/// [`check_consistency`](SieveCache::check_consistency) after each step.
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq)]
+pub enum Operation<K, V> {
+    /// [`SieveCache::insert`].
+    Insert(K, V),
+    /// [`SieveCache::get`].
+    Get(K),
+    /// [`SieveCache::get_mut`], replacing the value.
+    GetMut(K, V),
+    /// [`SieveCache::remove`].
+    Remove(K),
+    /// [`SieveCache::evict_one`].
+    Evict,
+    /// [`SieveCache::set_capacity`], with the capacity reduced modulo 16 and never 0, so that
+    /// evictions stay frequent.
+    SetCapacity(u8),
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Operation<K, V> {
+    /// Apply the operation to `cache`, returning the value it reads, replaces or removes.
+    ///
+    /// Failures, such as an insertion rejected because eviction is blocked, are ignored.
+    pub fn apply<S: BuildHasher>(self, cache: &mut SieveCache<K, V, S>) -> Option<V> {
+        match self {
+            Operation::Insert(key, value) => {
+                cache.insert(key, value);
+                None
+            }
+            Operation::Get(key) => cache.get(&key).cloned(),
+            Operation::GetMut(key, value) => cache
+                .get_mut(&key)
+                .map(|slot| std::mem::replace(slot, value)),
+            Operation::Remove(key) => cache.remove(&key),
+            Operation::Evict => {
+                cache.evict_one();
+                None
+            }
+            Operation::SetCapacity(capacity) => {
+                let _ = cache.set_capacity(capacity as usize % 16 + 1);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use std::cell::Cell;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ptr::{self, NonNull};
+
+use crate::{EvictCondition, Node};
+
+/// The algorithm used to pick which entry to evict.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// SIEVE: entries that were accessed since the hand last passed them get a second chance.
+    #[default]
+    Sieve,
+    /// First in, first out: the oldest entry is evicted, regardless of accesses.
+    ///
+    /// Unlike an LRU cache, accessing an entry never changes its position, which is what SIEVE
+    /// degrades to when no entry is ever accessed twice.
+    Fifo,
+    /// Least recently used: looking an entry up or updating it moves it to the head of the list,
+    /// and the tail is evicted.
+    ///
+    /// This relinks the entry on every hit, which SIEVE avoids. Every operation that marks an
+    /// entry as visited moves it: lookups such as [`get`] and [`get_mut`], updates through
+    /// [`insert`], and [`access_mut`] the first time its guard is dereferenced. Reads that leave
+    /// the visited flag alone, such as [`peek`], don't.
+    ///
+    /// [`get`]: crate::SieveCache::get
+    /// [`get_mut`]: crate::SieveCache::get_mut
+    /// [`insert`]: crate::SieveCache::insert
+    /// [`access_mut`]: crate::SieveCache::access_mut
+    /// [`peek`]: crate::SieveCache::peek
+    Lru,
+}
+
+/// The direction in which the hand of a cache sweeps its entries when looking for one to evict.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScanDirection {
+    /// From the oldest entry toward the most recently inserted one, as described by SIEVE.
+    #[default]
+    TailToHead,
+    /// From the most recently inserted entry toward the oldest one.
+    HeadToTail,
+}
+
+/// A handle to one entry of a cache, handed to [`EvictionPolicy`] hooks.
+pub struct NodeRef<'a, K: Eq + Hash + Clone, V> {
+    pub(crate) node: NonNull<Node<K, V>>,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<K: Eq + Hash + Clone, V> Clone for NodeRef<'_, K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Copy for NodeRef<'_, K, V> {}
+
+impl<'a, K: Eq + Hash + Clone, V> NodeRef<'a, K, V> {
+    pub(crate) fn new(node: NonNull<Node<K, V>>) -> Self {
+        Self {
+            node,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return the key of the entry.
+    pub fn key(&self) -> &'a K {
+        unsafe { &(*self.node.as_ptr()).key }
+    }
+
+    /// Return the value of the entry.
+    pub fn value(&self) -> &'a V {
+        unsafe { &(*self.node.as_ptr()).value }
+    }
+
+    /// Return the visited flag of the entry.
+    pub fn visited(&self) -> bool {
+        unsafe { ptr::addr_of!((*self.node.as_ptr()).visited).read() }
+    }
+
+    /// Set the visited flag of the entry.
+    pub fn set_visited(&self, visited: bool) {
+        unsafe { ptr::addr_of_mut!((*self.node.as_ptr()).visited).write(visited) }
+    }
+}
+
+/// The entries of a cache, from the most recently inserted (head) to the oldest (tail).
+pub struct NodeList<'a, K: Eq + Hash + Clone, V> {
+    pub(crate) head: Option<NonNull<Node<K, V>>>,
+    pub(crate) tail: Option<NonNull<Node<K, V>>>,
+    pub(crate) hand: Option<NonNull<Node<K, V>>>,
+    pub(crate) len: usize,
+    pub(crate) evict_condition: Option<EvictCondition<K, V>>,
+    pub(crate) max_scan: usize,
+    pub(crate) direction: ScanDirection,
+    // Counts the entries that the evict condition refused to evict
+    pub(crate) vetoes: Cell<u64>,
+    pub(crate) marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K: Eq + Hash + Clone, V> NodeList<'a, K, V> {
+    /// Return the number of entries.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return the most recently inserted entry.
+    pub fn head(&self) -> Option<NodeRef<'a, K, V>> {
+        self.head.map(NodeRef::new)
+    }
+
+    /// Return the oldest entry.
+    pub fn tail(&self) -> Option<NodeRef<'a, K, V>> {
+        self.tail.map(NodeRef::new)
+    }
+
+    /// Return the entry that precedes the last evicted one, where SIEVE resumes its sweep.
+    pub fn hand(&self) -> Option<NodeRef<'a, K, V>> {
+        self.hand.map(NodeRef::new)
+    }
+
+    /// Return the entry inserted right after `node`, moving toward the head.
+    pub fn prev(&self, node: NodeRef<'a, K, V>) -> Option<NodeRef<'a, K, V>> {
+        unsafe { (*node.node.as_ptr()).prev.map(NodeRef::new) }
+    }
+
+    /// Return the entry inserted right before `node`, moving toward the tail.
+    pub fn next(&self, node: NodeRef<'a, K, V>) -> Option<NodeRef<'a, K, V>> {
+        unsafe { (*node.node.as_ptr()).next.map(NodeRef::new) }
+    }
+
+    /// Return the direction in which the hand sweeps the entries.
+    pub fn direction(&self) -> ScanDirection {
+        self.direction
+    }
+
+    /// Return the entry where a sweep starts: the hand, or the end the hand starts from.
+    pub fn scan_start(&self) -> Option<NodeRef<'a, K, V>> {
+        self.hand().or_else(|| self.scan_origin())
+    }
+
+    /// Return the entry a sweep moves to after `node`, wrapping around at the end of the list.
+    pub fn scan_next(&self, node: NodeRef<'a, K, V>) -> Option<NodeRef<'a, K, V>> {
+        let next = match self.direction {
+            ScanDirection::TailToHead => self.prev(node),
+            ScanDirection::HeadToTail => self.next(node),
+        };
+        next.or_else(|| self.scan_origin())
+    }
+
+    fn scan_origin(&self) -> Option<NodeRef<'a, K, V>> {
+        match self.direction {
+            ScanDirection::TailToHead => self.tail(),
+            ScanDirection::HeadToTail => self.head(),
+        }
+    }
+
+    /// Return the number of entries an eviction may examine before it has to ignore visited flags.
+    pub fn max_scan(&self) -> usize {
+        self.max_scan
+    }
+
+    /// Return `true` if the cache's evict condition, if any, allows evicting `node`.
+    pub fn can_evict(&self, node: NodeRef<'a, K, V>) -> bool {
+        let allowed = self
+            .evict_condition
+            .is_none_or(|cond| cond.allows(node.key(), node.value(), node.visited()));
+        if !allowed {
+            self.vetoes.set(self.vetoes.get() + 1);
+        }
+        allowed
+    }
+}
+
+/// An algorithm deciding which entry of a cache gets evicted.
+///
+/// The cache owns the list and the map; a policy only maintains the visited flags through the
+/// hooks and picks a victim when room is needed.
+pub trait EvictionPolicy<K: Eq + Hash + Clone, V> {
+    /// Return a short name for the policy.
+    fn name(&self) -> &'static str;
+
+    /// Called when an existing entry is looked up or updated.
+    fn on_access(&mut self, _node: NodeRef<'_, K, V>) {}
+
+    /// Called after a new entry has been linked at the head of the list.
+    fn on_insert(&mut self, _node: NodeRef<'_, K, V>) {}
+
+    /// Return the entry to evict, or `None` if nothing can be evicted.
+    ///
+    /// The returned entry must belong to `list`. The cache moves the hand to the entry following it
+    /// in the scan direction.
+    fn select_victim<'a>(&mut self, list: &NodeList<'a, K, V>) -> Option<NodeRef<'a, K, V>>;
+}
+
+/// The SIEVE eviction policy.
+///
+/// When every entry is visited, the sweep clears all the flags on its first lap, wraps around, and
+/// evicts the first entry it cleared, i.e. the one it started from. A sweep only gives up when the
+/// evict condition blocks every entry, after a second lap if the first one cleared any flag. It
+/// then leaves the hand where it was: the next sweep starts from the same entry, or from the tail
+/// if the hand was never set.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sieve;
+
+impl<K: Eq + Hash + Clone, V> EvictionPolicy<K, V> for Sieve {
+    fn name(&self) -> &'static str {
+        "SIEVE"
+    }
+
+    fn on_access(&mut self, node: NodeRef<'_, K, V>) {
+        node.set_visited(true);
+    }
+
+    fn select_victim<'a>(&mut self, list: &NodeList<'a, K, V>) -> Option<NodeRef<'a, K, V>> {
+        let mut node = list.scan_start();
+        let mut scanned = 0;
+        let mut cleared = false;
+        while let Some(node_) = node {
+            // After a first lap, every entry is unvisited: a second lap can only find a victim
+            // among the entries whose flag the first one cleared.
+            let laps = if cleared { 2 } else { 1 };
+            if scanned >= laps * list.len() {
+                // We cannot evict anything
+                return None;
+            }
+            scanned += 1;
+            // An entry is evicted iff it is unvisited (or the scan budget is spent) and the evict
+            // condition allows it; the condition is only consulted for such entries.
+            let unprotected = !node_.visited() || scanned > list.max_scan();
+            if unprotected && list.can_evict(node_) {
+                return Some(node_);
+            }
+            if node_.visited() {
+                cleared = true;
+                node_.set_visited(false);
+            }
+            node = list.scan_next(node_);
+        }
+        None
+    }
+}
+
+/// The FIFO eviction policy, which ignores accesses.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Fifo;
+
+impl<K: Eq + Hash + Clone, V> EvictionPolicy<K, V> for Fifo {
+    fn name(&self) -> &'static str {
+        "FIFO"
+    }
+
+    fn select_victim<'a>(&mut self, list: &NodeList<'a, K, V>) -> Option<NodeRef<'a, K, V>> {
+        let mut node = list.scan_start();
+        let mut scanned = 0;
+        while let Some(node_) = node {
+            if scanned >= list.len() {
+                return None;
+            }
+            scanned += 1;
+            if list.can_evict(node_) {
+                return Some(node_);
+            }
+            node = list.scan_next(node_);
+        }
+        None
+    }
+}
+
+/// The LRU eviction policy, which evicts the oldest entry it is allowed to.
+///
+/// Moving accessed entries to the head is done by the cache for [`Policy::Lru`]: this type alone,
+/// used as a custom policy, behaves like [`Fifo`] without resuming at the hand.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lru;
+
+impl<K: Eq + Hash + Clone, V> EvictionPolicy<K, V> for Lru {
+    fn name(&self) -> &'static str {
+        "LRU"
+    }
+
+    fn select_victim<'a>(&mut self, list: &NodeList<'a, K, V>) -> Option<NodeRef<'a, K, V>> {
+        let mut node = list.tail();
+        while let Some(node_) = node {
+            if list.can_evict(node_) {
+                return Some(node_);
+            }
+            node = list.prev(node_);
+        }
+        None
+    }
+}
+
+/// A custom policy that can be cloned along with the cache using it.
+pub(crate) trait BoxedPolicy<K: Eq + Hash + Clone, V>: EvictionPolicy<K, V> + Send {
+    fn box_clone(&self) -> Box<dyn BoxedPolicy<K, V>>;
+}
+
+impl<K, V, P> BoxedPolicy<K, V> for P
+where
+    K: Eq + Hash + Clone,
+    P: EvictionPolicy<K, V> + Clone + Send + 'static,
+{
+    fn box_clone(&self) -> Box<dyn BoxedPolicy<K, V>> {
+        Box::new(self.clone())
+    }
+}
+
+/// The policy of a cache, dispatching statically to the built-in policies.
+pub(crate) enum PolicyImpl<K: Eq + Hash + Clone, V> {
+    Sieve(Sieve),
+    Fifo(Fifo),
+    Lru(Lru),
+    Custom(Box<dyn BoxedPolicy<K, V>>),
+}
+
+impl<K: Eq + Hash + Clone, V> Clone for PolicyImpl<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            PolicyImpl::Sieve(policy) => PolicyImpl::Sieve(*policy),
+            PolicyImpl::Fifo(policy) => PolicyImpl::Fifo(*policy),
+            PolicyImpl::Lru(policy) => PolicyImpl::Lru(*policy),
+            PolicyImpl::Custom(policy) => PolicyImpl::Custom(policy.box_clone()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> From<Policy> for PolicyImpl<K, V> {
+    fn from(policy: Policy) -> Self {
+        match policy {
+            Policy::Sieve => PolicyImpl::Sieve(Sieve),
+            Policy::Fifo => PolicyImpl::Fifo(Fifo),
+            Policy::Lru => PolicyImpl::Lru(Lru),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> PolicyImpl<K, V> {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            PolicyImpl::Sieve(policy) => EvictionPolicy::<K, V>::name(policy),
+            PolicyImpl::Fifo(policy) => EvictionPolicy::<K, V>::name(policy),
+            PolicyImpl::Lru(policy) => EvictionPolicy::<K, V>::name(policy),
+            PolicyImpl::Custom(policy) => policy.name(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn on_access(&mut self, node: NonNull<Node<K, V>>) {
+        match self {
+            PolicyImpl::Sieve(policy) => policy.on_access(NodeRef::new(node)),
+            PolicyImpl::Fifo(policy) => policy.on_access(NodeRef::new(node)),
+            PolicyImpl::Lru(policy) => policy.on_access(NodeRef::new(node)),
+            PolicyImpl::Custom(policy) => policy.on_access(NodeRef::new(node)),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn on_insert(&mut self, node: NonNull<Node<K, V>>) {
+        match self {
+            PolicyImpl::Sieve(policy) => policy.on_insert(NodeRef::new(node)),
+            PolicyImpl::Fifo(policy) => policy.on_insert(NodeRef::new(node)),
+            PolicyImpl::Lru(policy) => policy.on_insert(NodeRef::new(node)),
+            PolicyImpl::Custom(policy) => policy.on_insert(NodeRef::new(node)),
+        }
+    }
+
+    pub(crate) fn select_victim(
+        &mut self,
+        list: &NodeList<'_, K, V>,
+    ) -> Option<NonNull<Node<K, V>>> {
+        let victim = match self {
+            PolicyImpl::Sieve(policy) => policy.select_victim(list),
+            PolicyImpl::Fifo(policy) => policy.select_victim(list),
+            PolicyImpl::Lru(policy) => policy.select_victim(list),
+            PolicyImpl::Custom(policy) => policy.select_victim(list),
+        };
+        victim.map(|node| node.node)
+    }
+}
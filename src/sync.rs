@@ -0,0 +1,186 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Deref, DerefMut};
+#[cfg(debug_assertions)]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::SieveCache;
+
+/// A [`SieveCache`] behind a mutex, that can be shared between threads.
+///
+/// Every operation locks the whole cache. Lookups take `&self`, and return clones of the values
+/// since references can't outlive the lock; use [`with_lock`](Self::with_lock) to run several
+/// operations under a single lock.
+///
+/// The cache can only be shared between threads if its keys and values can be sent to other
+/// threads:
+///
+/// ```compile_fail,E0277
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<sieve_cache::SyncSieveCache<std::rc::Rc<u32>, u32>>();
+/// ```
+pub struct SyncSieveCache<K: Eq + Hash + Clone, V, S = RandomState> {
+    inner: Mutex<SieveCache<K, V, S>>,
+    len: AtomicUsize,
+    /// The id of the thread holding the lock, or 0, to catch reentrant calls in debug builds.
+    #[cfg(debug_assertions)]
+    owner: AtomicU64,
+}
+
+impl<K: Eq + Hash + Clone, V> SyncSieveCache<K, V> {
+    /// Create a new cache with the given capacity.
+    pub fn new(capacity: usize) -> Result<Self, &'static str> {
+        Ok(SieveCache::new(capacity)?.into())
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> From<SieveCache<K, V, S>>
+    for SyncSieveCache<K, V, S>
+{
+    fn from(cache: SieveCache<K, V, S>) -> Self {
+        Self {
+            len: AtomicUsize::new(cache.len()),
+            inner: Mutex::new(cache),
+            #[cfg(debug_assertions)]
+            owner: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> SyncSieveCache<K, V, S> {
+    /// Return the capacity of the cache.
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity()
+    }
+
+    /// Return the number of cached values.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Return `true` when no values are currently cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the number of cached values without locking the cache.
+    ///
+    /// The count is updated when a mutating operation releases the lock, so it may momentarily lag
+    /// behind operations running on other threads. This is meant for cheap monitoring and load
+    /// shedding, not for decisions that need an exact count.
+    #[inline]
+    pub fn approx_len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Return `true` if there is a value in the cache mapped to by `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+    {
+        self.lock().contains_key(key)
+    }
+
+    /// Return a clone of the value mapped to by `key`, marking the entry as visited.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Q: Hash + Eq + ?Sized,
+        K: Borrow<Q>,
+        V: Clone,
+    {
+        self.lock().get_cloned(key)
+    }
+
+    /// Map `key` to `value` in the cache, possibly evicting old entries.
+    ///
+    /// This returns `true` if a new entry was inserted, like the first element returned by
+    /// [`SieveCache::insert`].
+    pub fn insert(&self, key: K, value: V) -> bool {
+        self.with_lock(|cache| cache.insert(key, value).0)
+    }
+
+    /// Remove the cache entry mapped to by `key`, returning its value.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.with_lock(|cache| cache.remove(key))
+    }
+
+    /// Run `f` with exclusive access to the cache.
+    ///
+    /// `f` must not use this `SyncSieveCache` again: the lock isn't reentrant. In debug builds,
+    /// doing so panics with a message pointing at the reentrant call; in release builds, it
+    /// deadlocks. Either way, the cache is never modified from within another operation.
+    pub fn with_lock<T, F: FnOnce(&mut SieveCache<K, V, S>) -> T>(&self, f: F) -> T {
+        let mut cache = self.lock();
+        let result = f(&mut cache);
+        self.len.store(cache.len(), Ordering::Relaxed);
+        result
+    }
+
+    /// Return the wrapped cache.
+    pub fn into_inner(self) -> SieveCache<K, V, S> {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock(&self) -> LockGuard<'_, K, V, S> {
+        #[cfg(debug_assertions)]
+        let thread = {
+            let thread = thread_id();
+            assert_ne!(
+                self.owner.load(Ordering::Relaxed),
+                thread,
+                "reentrant use of a SyncSieveCache from a closure running under its lock"
+            );
+            thread
+        };
+        let guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        #[cfg(debug_assertions)]
+        self.owner.store(thread, Ordering::Relaxed);
+        LockGuard { cache: self, guard }
+    }
+}
+
+/// The locked cache, which records that its thread no longer holds the lock when dropped.
+struct LockGuard<'a, K: Eq + Hash + Clone, V, S> {
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    cache: &'a SyncSieveCache<K, V, S>,
+    guard: MutexGuard<'a, SieveCache<K, V, S>>,
+}
+
+impl<K: Eq + Hash + Clone, V, S> Deref for LockGuard<'_, K, V, S> {
+    type Target = SieveCache<K, V, S>;
+
+    fn deref(&self) -> &SieveCache<K, V, S> {
+        &self.guard
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S> DerefMut for LockGuard<'_, K, V, S> {
+    fn deref_mut(&mut self) -> &mut SieveCache<K, V, S> {
+        &mut self.guard
+    }
+}
+
+impl<K: Eq + Hash + Clone, V, S> Drop for LockGuard<'_, K, V, S> {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        self.cache.owner.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Return a nonzero id for the current thread.
+#[cfg(debug_assertions)]
+fn thread_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local!(static ID: u64 = NEXT.fetch_add(1, Ordering::Relaxed));
+    ID.with(|id| *id)
+}